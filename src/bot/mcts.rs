@@ -2,9 +2,14 @@
 
 use crate::{ bot::botlogic::BotLogic, game::Game, grid::Grid, player_move::PlayerMove, Size };
 
+use log::{ trace, warn };
+
 use rand::Rng;
 
-pub(crate) struct MCTSBot {}
+pub(crate) struct MCTSBot {
+    /// Number of search iterations per move.
+    iterations: u32,
+}
 impl BotLogic for MCTSBot {
     /// Generates a move using the MCTS algorithm based on the current game state.
     ///
@@ -19,11 +24,12 @@ impl BotLogic for MCTSBot {
     fn generate_move(&self, id: i32, game: &Game) -> Size {
         let mut algorithm = MCTSAlgorithm::new(id, &game.grid, game.win_length);
 
-        let max_iter = 10000;
+        let max_iter = self.iterations;
+        let (player_list, current_turn) = game.turn_state();
 
         for i in 0..max_iter {
-            println!("MCTS: Iterating... {}/{}", i + 1, max_iter);
-            algorithm.iterate(&game.player_list, game.current_turn, &game.grid, game.win_length);
+            trace!("MCTS: Iterating... {}/{}", i + 1, max_iter);
+            algorithm.iterate(&player_list, current_turn, &game.grid, game.win_length);
         }
 
         algorithm.find_best_move()
@@ -41,11 +47,15 @@ impl BotLogic for MCTSBot {
 impl MCTSBot {
     /// Creates a new instance of `MCTSBot`.
     ///
+    /// # Arguments
+    ///
+    /// * `iterations`: The number of search iterations per move.
+    ///
     /// # Returns
     ///
     /// A new `MCTSBot` instance.
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(iterations: u32) -> Self {
+        Self { iterations }
     }
 }
 #[derive(Debug)]
@@ -69,7 +79,7 @@ impl MCTSAlgorithm {
         let nodes = Node::from_possible_moves(grid.get_possible_moves(id), grid, win_length);
         Self {
             total_iterations: nodes.len() as u32,
-            nodes: nodes,
+            nodes,
         }
     }
 
@@ -127,8 +137,8 @@ impl MCTSAlgorithm {
         self.nodes
             .iter()
             .max_by(|x, y|
-                ((x.score as f32) / (x.visit_counter as f32)).total_cmp(
-                    &((y.score as f32) / (y.visit_counter as f32))
+                (x.score / (x.visit_counter as f32)).total_cmp(
+                    &(y.score / (y.visit_counter as f32))
                 )
             )
             .expect("No node found")
@@ -162,13 +172,13 @@ impl Node {
         let mut moves = vec![m.clone()];
         grid.add(m.clone());
         let mut result = grid.check_win(&m.position, win_length);
-        let won = result.len() > 0;
+        let won = !result.is_empty();
         moves.append(&mut result);
 
         let possible_moves = grid.get_possible_moves_size();
 
-        let s = Self {
-            moves: moves,
+        Self {
+            moves,
             children: Vec::new(),
             score: if won {
                 1.0
@@ -186,8 +196,7 @@ impl Node {
             } else {
                 None
             },
-        };
-        s
+        }
     }
 
     /// Creates a vector of `Node` instances from a list of possible moves.
@@ -221,7 +230,7 @@ impl Node {
     pub fn get_uct_score(&self, parent_visit_counter: u32) -> f32 {
         let exploration_parameter = (2.0f32).sqrt();
 
-        (self.score as f32) / (self.visit_counter as f32) +
+        self.score / (self.visit_counter as f32) +
             exploration_parameter *
                 ((parent_visit_counter as f32).log10() / (self.visit_counter as f32)).sqrt()
     }
@@ -267,9 +276,9 @@ impl Node {
         mut grid: Grid
     ) -> (usize, f32) {
         self.visit_counter += 1;
-        if self.win_result.is_some() {
-            self.score += self.win_result.unwrap();
-            return (current_turn, self.win_result.unwrap());
+        if let Some(win_result) = self.win_result {
+            self.score += win_result;
+            return (current_turn, win_result);
         }
 
         grid.add_range(&self.moves);
@@ -280,21 +289,19 @@ impl Node {
                 x.get_uct_score(self.visit_counter).total_cmp(&y.get_uct_score(self.visit_counter))
             );
 
-        if selected.is_none() && self.possible_moves.len() == 0 {
-            if self.possible_moves.len() == 0 {
+        if selected.is_none() && self.possible_moves.is_empty() {
+            if self.possible_moves.is_empty() {
                 return (current_turn, self.score);
             }
-        } else if selected.is_none() || self.possible_moves.len() > 0 {
-            drop(selected);
-
+        } else if selected.is_none() || !self.possible_moves.is_empty() {
             self.expand(players[(current_turn + 1) % players.len()], &grid, win_length);
             let child = self.children.last_mut().unwrap();
 
             grid.add_range(&child.moves);
 
-            if child.possible_moves.len() > 0 {
+            if !child.possible_moves.is_empty() {
                 if child.score != 0.0 {
-                    println!("score:{}", child.score); // This shouldn't happen
+                    warn!("score:{}", child.score); // This shouldn't happen
                     panic!();
                 }
 
@@ -349,11 +356,11 @@ impl Node {
         grid: &mut Grid,
         self_id: usize,
         mut current_turn: usize,
-        players: &Vec<i32>,
+        players: &[i32],
         win_length: u32,
         mut possible_moves: Vec<Size>
     ) -> f32 {
-        while possible_moves.len() > 0 {
+        while !possible_moves.is_empty() {
             let pos = possible_moves.remove(rand::thread_rng().gen_range(0..possible_moves.len()));
 
             grid.add(PlayerMove::new(players[current_turn], pos));
@@ -361,9 +368,9 @@ impl Node {
 
             grid.add_range(&result);
 
-            if result.len() > 0 && current_turn == self_id {
+            if !result.is_empty() && current_turn == self_id {
                 return 1.0;
-            } else if result.len() > 0 {
+            } else if !result.is_empty() {
                 return -1.0;
             }
 
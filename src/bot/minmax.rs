@@ -2,8 +2,13 @@
 
 use crate::{ bot::botlogic::BotLogic, game::Game, grid::Grid, player_move::PlayerMove, Size };
 
+use log::{ debug, trace };
+
 /// A struct that represents a bot employing the Minimax algorithm to select moves.
-pub(crate) struct MinMaxBot {}
+pub(crate) struct MinMaxBot {
+    /// Maximum number of positions to evaluate, used to pick the search depth.
+    complexity: u64,
+}
 impl BotLogic for MinMaxBot {
     /// Generates a move using Minimax reasoning based on the current game state.
     ///
@@ -16,7 +21,7 @@ impl BotLogic for MinMaxBot {
     ///
     /// A `Size` representing the determined optimal move, or the move (0, 0) if no valid moves are available.
     fn generate_move(&self, id: i32, game: &Game) -> Size {
-        Self::get_best_move(id, game).unwrap_or(Size::new(0, 0))
+        self.get_best_move(id, game).unwrap_or(Size::new(0, 0))
     }
 
     /// Returns a string representing the bot logic type ("minmax").
@@ -31,11 +36,15 @@ impl BotLogic for MinMaxBot {
 impl MinMaxBot {
     /// Creates a new instance of `MinMaxBot`.
     ///
+    /// # Arguments
+    ///
+    /// * `complexity`: The maximum number of positions to evaluate per move.
+    ///
     /// # Returns
     ///
     /// A new `MinMaxBot` instance.
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(complexity: u64) -> Self {
+        Self { complexity }
     }
 
     /// Calculates and returns the move determined to be best using the Minimax algorithm.
@@ -48,18 +57,18 @@ impl MinMaxBot {
     /// # Returns
     ///
     /// An `Option<Size>` representing the move deemed as best, or `None` if no valid moves exist.
-    fn get_best_move(id: i32, game: &Game) -> Option<Size> {
+    fn get_best_move(&self, id: i32, game: &Game) -> Option<Size> {
         let moves = game.grid.get_possible_moves(id);
 
-        if moves.len() < 1 {
+        if moves.is_empty() {
             return None;
         }
 
         let depth: u32 = get_depth(
             moves.len().try_into().expect("Could not convert usize to u32"),
-            100000
+            self.complexity.into()
         ) as u32;
-        println!("MINMAX | Chosen depth: {}", depth);
+        debug!("MINMAX | Chosen depth: {}", depth);
 
         let mut move_counter = 0;
         let total = get_complexity(
@@ -67,19 +76,20 @@ impl MinMaxBot {
             depth.into()
         );
 
+        let (player_list, current_turn) = game.turn_state();
         let (high_score, best_move) = Self::find_best_move(
             moves,
             &game.grid,
-            &game.player_list,
+            &player_list,
             &game.win_length,
-            game.current_turn,
+            current_turn,
             id,
             depth,
             &mut move_counter,
             &total
         );
 
-        println!("Proceeding with move {:?} with score {:?}", best_move, high_score);
+        debug!("Proceeding with move {:?} with score {:?}", best_move, high_score);
         Some(best_move)
     }
 
@@ -100,6 +110,8 @@ impl MinMaxBot {
     /// # Returns
     ///
     /// A `Vec<i32>` representing the scores for each player based on the simulation outcome.
+    // `u128::is_multiple_of` would need Rust 1.87
+    #[allow(clippy::too_many_arguments, clippy::manual_is_multiple_of)]
     fn get_score(
         m: &PlayerMove,
         mut grid: Grid,
@@ -112,7 +124,7 @@ impl MinMaxBot {
         total: &u128
     ) -> Vec<i32> {
         if *move_counter % 100000 == 0 {
-            println!("Processing move {}/{} at depth: {}", move_counter, total, depth);
+            trace!("Processing move {}/{} at depth: {}", move_counter, total, depth);
         }
         *move_counter += 1;
 
@@ -122,14 +134,14 @@ impl MinMaxBot {
 
         let moves = grid.check_win(&m.position, *win_length);
 
-        let won = moves.len() > 0;
+        let won = !moves.is_empty();
 
         if won {
             sum[current_turn] = 2;
             return sum;
         }
 
-        if depth <= 0 {
+        if depth == 0 {
             return vec![1; player_list.len()];
         }
 
@@ -139,7 +151,7 @@ impl MinMaxBot {
 
         let possible_moves = grid.get_possible_moves(player_list[next_turn]);
 
-        if possible_moves.len() > 0 {
+        if !possible_moves.is_empty() {
             let (high_score, _best_move) = Self::find_best_move(
                 possible_moves,
                 &grid,
@@ -178,7 +190,7 @@ impl MinMaxBot {
     ///
     /// A tuple containing a `Vec<i32>` representing the scores for each player based on the
     /// simulation outcome, and a `Size` representing the move determined to be optimal.
-
+    #[allow(clippy::too_many_arguments)]
     fn find_best_move(
         moves: Vec<PlayerMove>,
         grid: &Grid,
@@ -226,7 +238,7 @@ impl MinMaxBot {
 ///
 /// A `u128` representing the estimated number of possible game states.
 fn get_complexity(num: u128, depth: u128) -> u128 {
-    if depth <= 0 {
+    if depth == 0 {
         return 1;
     }
     match num {
@@ -255,7 +267,7 @@ fn get_depth(num: u128, complexity: u128) -> u128 {
         depth += 1;
     }
 
-    return depth;
+    depth
 }
 
 #[test]
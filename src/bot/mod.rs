@@ -5,12 +5,34 @@ mod random;
 mod minmax;
 mod mcts;
 
-use std::{ sync::{ mpsc::{ self, Receiver }, Arc, Mutex }, thread::Builder };
+use std::{ sync::{ Arc, Mutex }, thread::Builder };
 
-use crate::{ game::Game, net::{ broadcast_players, MessageEvent }, player::Player };
+use log::{ debug, info, warn };
+use serde::Deserialize;
+use tokio::sync::mpsc::{ self, UnboundedReceiver };
+
+use crate::{ game::Game, net::{ broadcast_players, ServerEvent }, player::Player };
 
 use self::{ botlogic::BotLogic, mcts::MCTSBot, minmax::MinMaxBot, random::RandomBot };
 
+/// Search budgets of the bot algorithms.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct BotConfig {
+    /// Number of iterations the MCTS bot runs per move.
+    pub mcts_iterations: u32,
+    /// Maximum number of positions the minimax bot evaluates per move.
+    pub minmax_complexity: u64,
+}
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            mcts_iterations: 10000,
+            minmax_complexity: 100000,
+        }
+    }
+}
+
 /// A struct representing a bot player in the game.
 pub(crate) struct Bot {
     /// The player object associated with the bot.
@@ -21,6 +43,9 @@ pub(crate) struct Bot {
     pub bot_type: String,
 }
 impl Bot {
+    /// Names of the bot algorithms that can be requested in `add_bot`.
+    pub const TYPES: [&'static str; 3] = ["random", "minmax", "mcts"];
+
     /// Creates a new `Bot` instance with the specified details.
     ///
     /// The bot automatically adds itself to the players in the game.
@@ -31,6 +56,7 @@ impl Bot {
     /// * `bot_type`: An optional string specifying the type of bot algorithm to use ("minmax", "mcts", etc.). Defaults to an empty string.
     /// * `players`: An `Arc<Mutex<Vec<Arc<Mutex<Player>>>>>` containing all players in the game.
     /// * `game`: An `Arc<Mutex<Game>>` representing the game instance.
+    /// * `config`: The search budgets for the bot algorithm.
     ///
     /// # Returns
     ///
@@ -39,18 +65,19 @@ impl Bot {
         id: i32,
         bot_type: Option<String>,
         players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>,
-        game: &Arc<Mutex<Game>>
+        game: &Arc<Mutex<Game>>,
+        config: BotConfig
     ) -> Arc<Mutex<Self>> {
-        let (tx, rx) = mpsc::channel();
+        let (tx, rx) = mpsc::unbounded_channel();
         let p_arc = Arc::new(Mutex::new(Player::new_bot(id, tx)));
 
         let s = Self {
             player: p_arc,
             game: game.clone(),
-            bot_type: bot_type.unwrap_or("".to_string()),
+            bot_type: bot_type.unwrap_or_default(),
         };
 
-        println!("Created new bot [{}]", s.bot_type);
+        info!("Created new bot [{}]", s.bot_type);
 
         players.lock().unwrap().push(s.player.clone());
         game.lock().unwrap().join_player_forced(&s.player);
@@ -61,7 +88,7 @@ impl Bot {
         Builder::new()
             .name(format!("Bot {} Thread", id))
             .spawn(move || {
-                Self::run(s_clone, rx);
+                Self::run(s_clone, rx, config);
             })
             .expect("Could not create thread");
         s_arc
@@ -72,14 +99,15 @@ impl Bot {
     /// # Arguments
     ///
     /// * `s`: A string representing the bot type ("minmax", "mcts", etc.).
+    /// * `config`: The search budgets for the bot algorithm.
     ///
     /// # Returns
     ///
     /// A `Box<dyn BotLogic>` containing the chosen bot logic implementation. Defaults to an instance of [`RandomBot`] if the string is not matched with any other algorithm name.
-    fn get_bot_logic(s: &str) -> Box<dyn BotLogic> {
+    fn get_bot_logic(s: &str, config: BotConfig) -> Box<dyn BotLogic> {
         match s {
-            "minmax" => { Box::new(MinMaxBot::new()) }
-            "mcts" => { Box::new(MCTSBot::new()) }
+            "minmax" => { Box::new(MinMaxBot::new(config.minmax_complexity)) }
+            "mcts" => { Box::new(MCTSBot::new(config.mcts_iterations)) }
             _ => { Box::new(RandomBot::new()) }
         }
     }
@@ -91,21 +119,29 @@ impl Bot {
     /// # Arguments
     ///
     /// * `bot`: An `Arc<Mutex<Bot>>` representing the bot instance.
-    /// * `rx`: A `Receiver<MessageEvent>` used to receive messages from the game thread.
-    fn run(bot: Arc<Mutex<Bot>>, rx: Receiver<MessageEvent>) {
-        let bot_logic = Self::get_bot_logic(bot.lock().unwrap().bot_type.as_str());
+    /// * `rx`: An `UnboundedReceiver<ServerEvent>` used to receive messages from the game thread.
+    /// * `config`: The search budgets for the bot algorithm.
+    fn run(bot: Arc<Mutex<Bot>>, mut rx: UnboundedReceiver<ServerEvent>, config: BotConfig) {
+        let bot_logic = Self::get_bot_logic(bot.lock().unwrap().bot_type.as_str(), config);
         bot.lock().unwrap().bot_type = bot_logic.get_name();
 
-        for msg in rx.iter() {
-            match msg.event.as_str() {
-                "turn" => {
-                    bot.lock().unwrap().process_turn(msg.content.as_str(), &bot_logic);
+        while let Some(msg) = rx.blocking_recv() {
+            match msg {
+                ServerEvent::Turn(turn) => {
+                    bot.lock().unwrap().process_turn(turn.player_id, bot_logic.as_ref());
+                }
+                ServerEvent::LeftGame(_) => {
+                    info!(
+                        "Bot {} left its game, stopping",
+                        bot.lock().unwrap().player.lock().unwrap().id
+                    );
+                    break;
                 }
                 _ => {
-                    println!(
-                        "Bot {}: Ignoring event: {}",
+                    debug!(
+                        "Bot {}: Ignoring event: {:?}",
                         bot.lock().unwrap().player.lock().unwrap().id,
-                        msg.event
+                        msg
                     );
                 }
             }
@@ -116,20 +152,15 @@ impl Bot {
     ///
     /// # Arguments
     ///
-    /// * `content`: A string representing the content of the received "turn" message.
-    /// * `bot_logic`: A reference to a `Box<dyn BotLogic>` containing the chosen bot logic implementation.
-    fn process_turn(&self, content: &str, bot_logic: &Box<dyn BotLogic>) {
-        if !self.game.lock().unwrap().is_running() {
-            return;
-        }
-        let cur_result: Result<i32, serde_json::Error> = serde_json::from_str(content);
-
-        if cur_result.is_err() {
-            println!("Failed to parse turn: {}", cur_result.err().unwrap());
+    /// * `cur`: The id of the player whose turn it is, taken from the received "turn" message.
+    /// * `bot_logic`: A reference to the chosen bot logic implementation.
+    fn process_turn(&self, cur: i32, bot_logic: &dyn BotLogic) {
+        let game_guard = self.game.lock().unwrap();
+        if !game_guard.is_running() || game_guard.is_paused() {
             return;
         }
+        drop(game_guard);
 
-        let cur = cur_result.unwrap();
         if cur != self.player.lock().unwrap().id {
             // Not my turn
             return;
@@ -142,16 +173,16 @@ impl Bot {
     ///
     /// # Arguments
     ///
-    /// * `bot_logic`: A reference to a `Box<dyn BotLogic>` containing the chosen bot logic implementation.
-    fn try_make_move(&self, bot_logic: &Box<dyn BotLogic>) {
-        println!("Processing move of bot type: {}", self.bot_type);
+    /// * `bot_logic`: A reference to the chosen bot logic implementation.
+    fn try_make_move(&self, bot_logic: &dyn BotLogic) {
+        debug!("Processing move of bot type: {}", self.bot_type);
 
         let m = bot_logic.generate_move(self.player.lock().unwrap().id, &self.game.lock().unwrap());
 
-        println!("Sending move {:?}", m);
+        debug!("Sending move {:?}", m);
 
         if !self.game.lock().unwrap().add_move(&self.player.clone(), m) {
-            println!("Illegal move, something went wrong!");
+            warn!("Illegal move, something went wrong!");
         }
     }
 }
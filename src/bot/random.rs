@@ -54,7 +54,7 @@ impl RandomBot {
     pub fn get_random_move(id: i32, grid: &Grid) -> Option<Size> {
         let moves = grid.get_possible_moves(id);
 
-        if moves.len() < 1 {
+        if moves.is_empty() {
             return None;
         }
 
@@ -11,22 +11,14 @@ pub(crate) struct Size {
 impl Size {
     pub fn new(x: u32, y: u32) -> Self {
         Self {
-            x: x,
-            y: y,
+            x,
+            y,
         }
     }
-    pub fn from_json(text: &str) -> Result<Self, String> {
-        from_json(text)
-    }
 }
 
 pub fn from_json<T>(text: &str) -> Result<T, String> where T: serde::de::DeserializeOwned {
-    let result: Result<T, serde_json::Error> = serde_json::from_str(text);
-    if result.is_ok() {
-        return Ok(result.unwrap());
-    }
-    let err_string = result.err().unwrap().to_string();
-    Err(err_string)
+    serde_json::from_str(text).map_err(|e| e.to_string())
 }
 
 pub fn get_object<T, P>(arr: &Arc<Mutex<Vec<Arc<Mutex<T>>>>>, predicate: P) -> Option<Arc<Mutex<T>>>
@@ -34,14 +26,9 @@ pub fn get_object<T, P>(arr: &Arc<Mutex<Vec<Arc<Mutex<T>>>>>, predicate: P) -> O
 {
     let guard = arr.lock().unwrap();
     let index = find_index(&guard, predicate);
-    if index.is_some() {
-        let result = Some(guard[index.unwrap()].clone());
-        drop(guard);
-        return result;
-    }
-    None
+    index.map(|i| guard[i].clone())
 }
-pub fn find_index<T, P>(arr: &Vec<Arc<Mutex<T>>>, predicate: P) -> Option<usize>
+pub fn find_index<T, P>(arr: &[Arc<Mutex<T>>], predicate: P) -> Option<usize>
     where P: FnMut(&Arc<Mutex<T>>) -> bool
 {
     arr.iter().position(predicate)
@@ -0,0 +1,279 @@
+//! Server configuration, read from a TOML file and overridden by command line arguments.
+
+use std::{ fs, path::PathBuf, time::Duration };
+
+use clap::Parser;
+use log::LevelFilter;
+use serde::{ Deserialize, Deserializer };
+
+use crate::{
+    bot::BotConfig,
+    game::GameConfig,
+    heartbeat::HeartbeatConfig,
+    ratelimit::RateLimitConfig,
+    session::SessionConfig,
+};
+
+/// Command line arguments. Every option overrides the matching setting of the configuration file.
+#[derive(Parser, Debug)]
+#[command(version, about = "Multiplayer tic-tac-toe server")]
+pub(crate) struct Cli {
+    /// Path of the TOML configuration file.
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Address to listen on. Can be given multiple times.
+    #[arg(short, long)]
+    pub listen: Vec<String>,
+    /// PEM file with the TLS certificate chain. Connections are served over `wss://` when set.
+    #[arg(long, requires = "key")]
+    pub cert: Option<String>,
+    /// PEM file with the TLS private key.
+    #[arg(long, requires = "cert")]
+    pub key: Option<String>,
+    /// Maximum number of players connected at once, bots included.
+    #[arg(long)]
+    pub max_players: Option<usize>,
+    /// Maximum number of games existing at once.
+    #[arg(long)]
+    pub max_games: Option<usize>,
+    /// Maximum width and height of a board.
+    #[arg(long)]
+    pub max_board_size: Option<u32>,
+    /// Maximum number of players a game can be created for.
+    #[arg(long)]
+    pub max_player_limit: Option<usize>,
+    /// Number of iterations the MCTS bot runs per move.
+    #[arg(long)]
+    pub mcts_iterations: Option<u32>,
+    /// Maximum number of positions the minimax bot evaluates per move.
+    #[arg(long)]
+    pub minmax_complexity: Option<u64>,
+    /// Seconds between WebSocket pings.
+    #[arg(long, value_parser = parse_seconds)]
+    pub ping_interval: Option<Duration>,
+    /// Seconds to wait for a pong before dropping the connection.
+    #[arg(long, value_parser = parse_seconds)]
+    pub pong_timeout: Option<Duration>,
+    /// Seconds without any request after which a client is disconnected.
+    #[arg(long, value_parser = parse_seconds)]
+    pub idle_timeout: Option<Duration>,
+    /// Seconds after which an empty or finished game is removed.
+    #[arg(long, value_parser = parse_seconds)]
+    pub teardown_delay: Option<Duration>,
+    /// Seconds a disconnected player can take to reconnect into its seat.
+    #[arg(long, value_parser = parse_seconds)]
+    pub grace_period: Option<Duration>,
+    /// One of off, error, warn, info, debug and trace. Applies to the server's own messages, libraries log warnings only.
+    #[arg(long)]
+    pub log_level: Option<LevelFilter>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Addresses to listen on.
+    pub listen: Vec<String>,
+    /// Serve `wss://` with this certificate and key instead of plain `ws://`.
+    pub tls: Option<TlsConfig>,
+    pub limits: ServerLimits,
+    pub bots: BotConfig,
+    pub games: GameConfig,
+    pub heartbeat: HeartbeatConfig,
+    pub sessions: SessionConfig,
+    pub rate_limits: RateLimitConfig,
+    pub log_level: LevelFilter,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: vec!["0.0.0.0:9001".to_string()],
+            tls: None,
+            limits: ServerLimits::default(),
+            bots: BotConfig::default(),
+            games: GameConfig::default(),
+            heartbeat: HeartbeatConfig::default(),
+            sessions: SessionConfig::default(),
+            rate_limits: RateLimitConfig::default(),
+            log_level: LevelFilter::Info,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TlsConfig {
+    /// PEM file with the certificate chain, leaf certificate first.
+    pub certificate: String,
+    /// PEM file with the private key.
+    pub private_key: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ServerLimits {
+    /// Maximum number of players connected at once, bots included.
+    pub max_players: usize,
+    /// Maximum number of games existing at once.
+    pub max_games: usize,
+    /// Maximum width and height of a board.
+    pub max_board_size: u32,
+    /// Maximum number of players a game can be created for.
+    pub max_player_limit: usize,
+}
+impl Default for ServerLimits {
+    fn default() -> Self {
+        Self {
+            max_players: 1000,
+            max_games: 200,
+            max_board_size: 100,
+            max_player_limit: 10,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration file given on the command line, if any, and applies the command line overrides.
+    ///
+    /// # Returns
+    ///
+    /// The configuration, or a description of what is wrong with it.
+    pub fn load(cli: Cli) -> Result<Self, String> {
+        let mut config = match &cli.config {
+            Some(path) => {
+                let content = fs
+                    ::read_to_string(path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                Self::from_toml(&content).map_err(|e| format!("{}: {}", path.display(), e))?
+            }
+            None => Self::default(),
+        };
+        config.apply(cli);
+
+        if config.listen.is_empty() {
+            return Err("No listen address given.".to_string());
+        }
+        Ok(config)
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|e| e.to_string())
+    }
+
+    /// Overrides the settings given on the command line.
+    fn apply(&mut self, cli: Cli) {
+        if !cli.listen.is_empty() {
+            self.listen = cli.listen;
+        }
+        if let (Some(certificate), Some(private_key)) = (cli.cert, cli.key) {
+            self.tls = Some(TlsConfig { certificate, private_key });
+        }
+        if let Some(max_players) = cli.max_players {
+            self.limits.max_players = max_players;
+        }
+        if let Some(max_games) = cli.max_games {
+            self.limits.max_games = max_games;
+        }
+        if let Some(max_board_size) = cli.max_board_size {
+            self.limits.max_board_size = max_board_size;
+        }
+        if let Some(max_player_limit) = cli.max_player_limit {
+            self.limits.max_player_limit = max_player_limit;
+        }
+        if let Some(mcts_iterations) = cli.mcts_iterations {
+            self.bots.mcts_iterations = mcts_iterations;
+        }
+        if let Some(minmax_complexity) = cli.minmax_complexity {
+            self.bots.minmax_complexity = minmax_complexity;
+        }
+        if let Some(ping_interval) = cli.ping_interval {
+            self.heartbeat.ping_interval = ping_interval;
+        }
+        if let Some(pong_timeout) = cli.pong_timeout {
+            self.heartbeat.pong_timeout = pong_timeout;
+        }
+        if let Some(idle_timeout) = cli.idle_timeout {
+            self.heartbeat.idle_timeout = idle_timeout;
+        }
+        if let Some(teardown_delay) = cli.teardown_delay {
+            self.games.teardown_delay = teardown_delay;
+        }
+        if let Some(grace_period) = cli.grace_period {
+            self.sessions.grace_period = grace_period;
+        }
+        if let Some(log_level) = cli.log_level {
+            self.log_level = log_level;
+        }
+    }
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let seconds: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+/// Deserializes a duration given as a number of seconds, for use with `#[serde(deserialize_with)]`.
+pub(crate) fn seconds<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where D: Deserializer<'de>
+{
+    let seconds = f64::deserialize(deserializer)?;
+    Duration::try_from_secs_f64(seconds).map_err(serde::de::Error::custom)
+}
+
+#[test]
+fn config_file_and_overrides() {
+    let config = Config::from_toml(
+        r#"
+        listen = ["127.0.0.1:9001", "[::1]:9001"]
+        log_level = "debug"
+
+        [limits]
+        max_games = 5
+
+        [bots]
+        mcts_iterations = 500
+
+        [heartbeat]
+        idle_timeout = 90
+        ping_interval = 2.5
+
+        [rate_limits.connection]
+        default = { burst = 5, per_second = 1.0 }
+        "#
+    ).unwrap();
+    assert_eq!(config.listen.len(), 2);
+    assert_eq!(config.log_level, LevelFilter::Debug);
+    assert_eq!(config.limits.max_games, 5);
+    assert_eq!(config.limits.max_players, ServerLimits::default().max_players);
+    assert_eq!(config.bots.mcts_iterations, 500);
+    assert_eq!(config.bots.minmax_complexity, 100000);
+    assert_eq!(config.heartbeat.idle_timeout, Duration::from_secs(90));
+    assert_eq!(config.heartbeat.ping_interval, Duration::from_millis(2500));
+    assert_eq!(config.rate_limits.connection.default.burst, 5);
+    assert!(config.rate_limits.connection.events.is_empty());
+
+    assert!(Config::from_toml(include_str!("../config.example.toml")).is_ok());
+    assert!(Config::from_toml("max_game = 5").is_err());
+    assert!(Config::from_toml("[heartbeat]\nidle_timeout = -1").is_err());
+
+    let mut config = config;
+    config.apply(
+        Cli::try_parse_from([
+            "tictactoe-server",
+            "--listen",
+            "0.0.0.0:9100",
+            "--max-games",
+            "10",
+            "--idle-timeout",
+            "60",
+            "--log-level",
+            "warn",
+        ]).unwrap()
+    );
+    assert_eq!(config.listen, vec!["0.0.0.0:9100".to_string()]);
+    assert_eq!(config.limits.max_games, 10);
+    assert_eq!(config.heartbeat.idle_timeout, Duration::from_secs(60));
+    assert_eq!(config.log_level, LevelFilter::Warn);
+    assert_eq!(config.bots.mcts_iterations, 500);
+
+    assert!(Cli::try_parse_from(["tictactoe-server", "--cert", "cert.pem"]).is_err());
+}
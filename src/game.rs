@@ -1,73 +1,229 @@
-use std::{ sync::{ mpsc::{ self, Receiver, Sender }, Arc, Mutex }, thread::Builder };
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{ mpsc::{ self, Receiver, RecvTimeoutError, Sender }, Arc, Mutex },
+    thread::Builder,
+    time::{ Duration, Instant },
+};
 
-use serde::Serialize;
+use log::{ debug, info, trace, warn };
+use rand::{ distributions::Alphanumeric, Rng };
+use serde::{ Deserialize, Serialize };
 use crate::{
-    bot::Bot,
+    bot::{ Bot, BotConfig },
     common::{ get_object, get_unique_id, Size },
+    config::{ seconds, ServerLimits },
     grid::Grid,
     net::{
+        broadcast_games,
         broadcast_players,
+        send_to_player,
+        ClockData,
+        ErrorCode,
         GameCreationData,
+        GameJoinData,
+        GameOverData,
+        GameUpdateData,
         InternalMessage,
         InternalMessageKind,
-        MessageEvent,
+        MatchTarget,
+        PauseData,
+        RoundEnd,
+        RoundOverData,
+        RoundOverReason,
+        RoundScore,
+        TurnOrder,
+        ServerEvent,
+        Standing,
         Status,
+        TimeControl,
+        TimeoutAction,
+        TurnData,
+        TurnTimeoutData,
+        UndoData,
+        Visibility,
     },
     player::Player,
     player_move::PlayerMove,
 };
 
-#[derive(Serialize)]
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct GameConfig {
+    /// Time after which a game without human players, or a finished game, is removed.
+    #[serde(deserialize_with = "seconds")]
+    pub teardown_delay: Duration,
+}
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            teardown_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A pending request to take back the last move.
+struct UndoRequest {
+    player_id: i32,
+    /// Human players that haven't accepted yet. Bots always accept.
+    waiting_for: Vec<i32>,
+}
+
 pub(crate) struct Game {
     pub id: u32,
-    #[serde(skip_serializing)]
     pub grid: Grid,
-    #[serde(skip_serializing)]
     pub tx: Sender<InternalMessage>,
-    pub player_list: Vec<i32>,
+    /// One seat per possible player, holding the id of the player sitting in it. Players keep their seat until they
+    /// leave and the turn goes around the seats in order.
+    seats: Vec<Option<i32>>,
+    /// Players watching the game. They receive its events but can't take part in it.
+    spectators: Vec<i32>,
+    /// Players banned by the creator. A reconnecting player keeps its id, so this covers its session as well.
+    banned: Vec<i32>,
+    visibility: Visibility,
+    /// Code needed to join the game if it is unlisted.
+    invite_code: Option<String>,
     creator: i32,
-    pub current_turn: usize,
+    /// The seat whose turn it is.
+    current_seat: usize,
     hotjoin: bool,
-    player_limit: usize,
     running: bool,
     pub win_length: u32,
     width: u32,
     height: u32,
+    match_target: Option<MatchTarget>,
+    /// Number of completed rounds.
+    rounds_played: u32,
+    /// Points scored in this game, keyed by player id.
+    scores: HashMap<i32, u32>,
+    /// Points scored in the current round, keyed by player id.
+    round_scores: HashMap<i32, u32>,
+    round_end: RoundEnd,
+    turn_order: TurnOrder,
+    /// Result of the last round, used to pick the first mover of the next one.
+    previous_round: Option<RoundOverData>,
+    /// True once the match target has been reached. A finished game can't be started again.
+    finished: bool,
+    /// When the game will be removed, set while it is empty or finished.
+    teardown_at: Option<Instant>,
+    time_control: Option<TimeControl>,
+    /// Remaining time banks of the chess clock, keyed by player id. Players without an entry have the initial time.
+    clocks: HashMap<i32, Duration>,
+    /// The clocks and the time the mover had used before each move of the round, restored when the move is undone.
+    clock_history: Vec<(HashMap<i32, Duration>, Duration)>,
+    /// When the current turn started.
+    turn_started: Instant,
+    /// When the current turn times out.
+    turn_deadline: Option<Instant>,
+    undo_request: Option<UndoRequest>,
+    casual: bool,
+    /// Players that asked for a pause which hasn't started yet.
+    pause_requests: Vec<i32>,
+    /// When the game was paused, set while it is paused.
+    paused_at: Option<Instant>,
+}
+
+/// The publicly visible part of a [`Game`], sent to clients in the `games` event.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct GameInfo {
+    pub id: u32,
+    pub player_list: Vec<i32>,
+    /// The player id in every seat of the game, `None` for empty seats.
+    pub seats: Vec<Option<i32>>,
+    /// Number of players watching the game.
+    pub spectators: usize,
+    pub creator: i32,
+    pub current_turn: usize,
+    pub hotjoin: bool,
+    pub player_limit: usize,
+    pub running: bool,
+    pub win_length: u32,
+    pub width: u32,
+    pub height: u32,
+    pub match_target: Option<MatchTarget>,
+    pub rounds_played: u32,
+    pub round_end: RoundEnd,
+    pub turn_order: TurnOrder,
+    pub casual: bool,
+    pub paused: bool,
+    pub finished: bool,
+    /// True if joining the game needs a password.
+    pub password_protected: bool,
+    /// Only set for unlisted games, which are shown to their creator and players only.
+    pub invite_code: Option<String>,
 }
+
 impl Game {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         parameters: &GameCreationData,
         game_id_counter: &Arc<Mutex<u32>>,
         creator: &Arc<Mutex<Player>>,
         players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>,
-        player_id_counter: &Arc<Mutex<i32>>
+        games: &Arc<Mutex<Vec<Arc<Mutex<Game>>>>>,
+        player_id_counter: &Arc<Mutex<i32>>,
+        bot_config: BotConfig,
+        config: GameConfig
     ) -> Arc<Mutex<Self>> {
         let (tx, rx) = mpsc::channel::<InternalMessage>();
         let mut id_counter_locked = game_id_counter.lock().unwrap();
         let instance = Self {
             id: *id_counter_locked,
             grid: Grid::new(parameters.size),
-            tx: tx,
-            player_list: Vec::new(),
+            tx,
+            seats: vec![None; parameters.player_limit],
+            spectators: Vec::new(),
+            banned: Vec::new(),
+            visibility: parameters.visibility.clone(),
+            invite_code: match parameters.visibility {
+                Visibility::Unlisted => Some(generate_invite_code()),
+                _ => None,
+            },
             creator: creator.lock().unwrap().id,
-            current_turn: 0,
+            current_seat: 0,
             hotjoin: parameters.hotjoin,
-            player_limit: parameters.player_limit,
             running: false,
             win_length: parameters.length_to_win,
             width: parameters.size.x,
             height: parameters.size.y,
+            match_target: parameters.match_target,
+            rounds_played: 0,
+            scores: HashMap::new(),
+            round_scores: HashMap::new(),
+            round_end: parameters.round_end,
+            turn_order: parameters.turn_order,
+            previous_round: None,
+            finished: false,
+            teardown_at: None,
+            time_control: parameters.time_control,
+            clocks: HashMap::new(),
+            clock_history: Vec::new(),
+            turn_started: Instant::now(),
+            turn_deadline: None,
+            undo_request: None,
+            casual: parameters.casual,
+            pause_requests: Vec::new(),
+            paused_at: None,
         };
         *id_counter_locked += 1;
         let self_arc = Arc::new(Mutex::new(instance));
         let players_clone = players.clone();
+        let games_clone = games.clone();
         Builder::new()
             .name(format!("Game {} Thread", *id_counter_locked))
             .spawn({
                 let self_arc_clone = self_arc.clone();
                 let player_id_counter_clone = player_id_counter.clone();
                 move || {
-                    Self::run(self_arc_clone, rx, players_clone, player_id_counter_clone);
+                    Self::run(
+                        self_arc_clone,
+                        rx,
+                        players_clone,
+                        games_clone,
+                        player_id_counter_clone,
+                        bot_config,
+                        config
+                    );
                 }
             })
             .expect("Could not create thread");
@@ -78,21 +234,161 @@ impl Game {
         self.running
     }
 
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Returns the ids of the seated players, in seat order.
+    fn seated(&self) -> impl Iterator<Item = i32> + '_ {
+        self.seats.iter().flatten().copied()
+    }
+
+    fn seat_of(&self, player_id: i32) -> Option<usize> {
+        self.seats.iter().position(|s| *s == Some(player_id))
+    }
+
+    /// Returns true if the player sits in one of the seats of the game.
+    pub fn is_player(&self, player_id: i32) -> bool {
+        self.seat_of(player_id).is_some()
+    }
+
+    /// Returns true if the player created the game and takes part in it. A creator watching the game as a spectator
+    /// can't manage it.
+    fn is_seated_creator(&self, player_id: i32) -> bool {
+        player_id == self.creator && self.is_player(player_id)
+    }
+
+    /// Returns the seated players in seat order, together with the index of the player whose turn it is among them.
+    pub fn turn_state(&self) -> (Vec<i32>, usize) {
+        let player_list: Vec<i32> = self.seated().collect();
+        let current_turn = self
+            .current_player()
+            .and_then(|id| player_list.iter().position(|p| *p == id))
+            .unwrap_or(0);
+        (player_list, current_turn)
+    }
+
+    pub fn info(&self) -> GameInfo {
+        let (player_list, current_turn) = self.turn_state();
+        GameInfo {
+            id: self.id,
+            player_list,
+            seats: self.seats.clone(),
+            spectators: self.spectators.len(),
+            creator: self.creator,
+            current_turn,
+            hotjoin: self.hotjoin,
+            player_limit: self.seats.len(),
+            running: self.running,
+            win_length: self.win_length,
+            width: self.width,
+            height: self.height,
+            match_target: self.match_target,
+            rounds_played: self.rounds_played,
+            round_end: self.round_end,
+            turn_order: self.turn_order,
+            casual: self.casual,
+            paused: self.is_paused(),
+            finished: self.finished,
+            password_protected: matches!(self.visibility, Visibility::Password { .. }),
+            invite_code: self.invite_code.clone(),
+        }
+    }
+
+    /// Returns true if the game is shown in the lobby.
+    pub fn is_listed(&self) -> bool {
+        self.visibility != Visibility::Unlisted
+    }
+
+    /// Checks the password or invite code given by a player joining or watching the game.
+    pub fn check_credentials(&self, join_data: &GameJoinData) -> Result<(), String> {
+        match &self.visibility {
+            Visibility::Public => Ok(()),
+            Visibility::Unlisted => {
+                if join_data.invite_code != self.invite_code {
+                    return Err("Wrong invite code.".to_string());
+                }
+                Ok(())
+            }
+            Visibility::Password { password } => {
+                if join_data.password.as_ref() != Some(password) {
+                    return Err("Wrong password.".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+
     pub fn run(
         game: Arc<Mutex<Game>>,
         rx: Receiver<InternalMessage>,
         players: Arc<Mutex<Vec<Arc<Mutex<Player>>>>>,
-        player_id_counter: Arc<Mutex<i32>>
+        games: Arc<Mutex<Vec<Arc<Mutex<Game>>>>>,
+        player_id_counter: Arc<Mutex<i32>>,
+        bot_config: BotConfig,
+        config: GameConfig
     ) {
-        for msg in rx.iter() {
+        loop {
+            let (teardown_at, turn_deadline) = {
+                let mut game_guard = game.lock().unwrap();
+                let teardown_at = game_guard.update_teardown(&players, config.teardown_delay);
+                (teardown_at, game_guard.turn_deadline())
+            };
+            let received = match teardown_at.into_iter().chain(turn_deadline).min() {
+                Some(deadline) => {
+                    rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => rx.recv().map_err(RecvTimeoutError::from),
+            };
+            let msg = match received {
+                Ok(msg) => msg,
+                Err(RecvTimeoutError::Timeout) => {
+                    if teardown_at.is_some_and(|t| t <= Instant::now()) {
+                        Self::teardown(&game, &players, &games);
+                        return;
+                    }
+                    Self::handle_turn_timeout(&game, &players, &games);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return;
+                }
+            };
+
             match msg.kind {
                 InternalMessageKind::PlayerJoin => {
-                    game.lock().unwrap().handle_player_join(&game, &msg.player);
+                    let mut game_guard = game.lock().unwrap();
+                    let id = msg.player.lock().unwrap().id;
+                    // A player whose join was queued twice keeps its seat
+                    if !game_guard.handle_player_join(&game, &msg.player) && !game_guard.is_player(id) {
+                        // The seats filled up while the join was queued
+                        let mut player_guard = msg.player.lock().unwrap();
+                        player_guard.joined_game = None;
+                        player_guard.joined_game_id = None;
+                        drop(player_guard);
+                        let left = ServerEvent::LeftGame(GameJoinData::new(game_guard.id));
+                        send_to_player(&msg.player, &left);
+                        Self::forget_bot(&msg.player, &players);
+                    }
+                    drop(game_guard);
                     broadcast_players(&players);
                 }
                 InternalMessageKind::PlayerMove => {
                     let mut game_guard = game.lock().unwrap();
                     let m = PlayerMove::new(msg.player.lock().unwrap().id, msg.position.unwrap());
+                    if
+                        !game_guard.running ||
+                        game_guard.is_paused() ||
+                        game_guard.current_player() != Some(m.player)
+                    {
+                        // The turn timed out, the game was paused or the player left while the move was queued
+                        continue;
+                    }
+                    let snapshot = (game_guard.clocks.clone(), game_guard.turn_started.elapsed());
+                    game_guard.clock_history.push(snapshot);
+                    game_guard.end_turn();
+                    // A pending undo request would now take back this move instead
+                    game_guard.undo_request = None;
                     game_guard.broadcast_move(&m, &players);
                     game_guard.grid.add(m.clone());
 
@@ -100,26 +396,67 @@ impl Game {
                         &m.position.clone(),
                         game_guard.win_length
                     );
-                    if moves.len() > 0 {
+                    let line: Vec<Size> = moves
+                        .iter()
+                        .map(|mv| mv.position)
+                        .collect();
+                    if !moves.is_empty() {
                         for mv in moves {
                             game_guard.broadcast_move(&mv, &players);
                             game_guard.grid.add(mv);
                         }
 
-                        println!("Adding score to player");
+                        debug!("Adding score to player");
                         msg.player.lock().unwrap().score += 1;
+                        *game_guard.scores.entry(m.player).or_default() += 1;
+                        *game_guard.round_scores.entry(m.player).or_default() += 1;
+                        broadcast_players(&players);
+                    }
+
+                    // Also ends the round early when the board isn't full but nobody can win anymore
+                    let round_result = if
+                        !line.is_empty() &&
+                        game_guard.round_end == RoundEnd::FirstLine
+                    {
+                        Some((RoundOverReason::Line, Some(m.player), line))
+                    } else if !game_guard.grid.can_complete_line(game_guard.win_length) {
+                        match game_guard.round_leader() {
+                            Some(leader) => Some((RoundOverReason::Points, Some(leader), Vec::new())),
+                            None => Some((RoundOverReason::Draw, None, Vec::new())),
+                        }
+                    } else {
+                        None
+                    };
+                    let round_over = round_result.is_some();
+                    if let Some((reason, winner, line)) = round_result {
+                        game_guard.end_round(reason, winner, line, &players);
+                    }
+
+                    if game_guard.is_target_reached() {
+                        game_guard.finish(&players);
+                        drop(game_guard);
                         broadcast_players(&players);
+                        broadcast_games(&players, &games);
+                        continue;
                     }
 
-                    if game_guard.grid.get_possible_moves(0).len() == 0 {
+                    if round_over {
                         game_guard.grid = Grid::new(game_guard.grid.size);
+                        game_guard.clock_history.clear();
+                        game_guard.round_scores.clear();
                         game_guard.broadcast_current_state(&players);
                     }
 
-                    game_guard.next_turn(&players);
+                    game_guard.next_turn(round_over, &players);
                 }
                 InternalMessageKind::PlayerLeave => {
                     game.lock().unwrap().remove_player(&msg.player, &players);
+                    // The game's player list and possibly its creator changed
+                    broadcast_players(&players);
+                    broadcast_games(&players, &games);
+                }
+                InternalMessageKind::PlayerDisconnect => {
+                    game.lock().unwrap().handle_disconnect(&msg.player, &players);
                 }
                 InternalMessageKind::PlayerReady => {
                     let mut game_guard = game.lock().unwrap();
@@ -129,19 +466,115 @@ impl Game {
                 }
                 InternalMessageKind::AddBot => {
                     let bot_id = get_unique_id(&player_id_counter);
-                    let _bot = Bot::new(bot_id, msg.bot_type, &players, &game);
+                    let _bot = Bot::new(bot_id, msg.bot_type, &players, &game, bot_config);
                 }
                 InternalMessageKind::CurrentState => {
                     game.lock().unwrap().send_current_state(&msg.player);
                 }
+                InternalMessageKind::Rejoin => {
+                    game.lock().unwrap().handle_rejoin(&msg.player);
+                }
+                InternalMessageKind::Spectate => {
+                    game.lock().unwrap().handle_spectator_join(&game, &msg.player);
+                    broadcast_players(&players);
+                    broadcast_games(&players, &games);
+                }
+                InternalMessageKind::PauseRequest => {
+                    game.lock().unwrap().handle_pause_request(&msg.player, &players);
+                    broadcast_games(&players, &games);
+                }
+                InternalMessageKind::Resume => {
+                    game.lock().unwrap().handle_resume(&msg.player, &players);
+                    broadcast_games(&players, &games);
+                }
+                InternalMessageKind::UndoRequest => {
+                    game.lock().unwrap().handle_undo_request(&msg.player, &players);
+                }
+                InternalMessageKind::UndoAccept | InternalMessageKind::UndoDecline => {
+                    let accept = matches!(msg.kind, InternalMessageKind::UndoAccept);
+                    game.lock().unwrap().handle_undo_response(&msg.player, accept, &players);
+                }
+                InternalMessageKind::Kick | InternalMessageKind::Ban => {
+                    let mut game_guard = game.lock().unwrap();
+                    let id = msg.player.lock().unwrap().id;
+                    if matches!(msg.kind, InternalMessageKind::Ban) {
+                        game_guard.banned.push(id);
+                    }
+                    info!("Player {} was removed from game {}", id, game_guard.id);
+                    game_guard.remove_player(&msg.player, &players);
+                    drop(game_guard);
+                    Self::forget_bot(&msg.player, &players);
+                    broadcast_players(&players);
+                    broadcast_games(&players, &games);
+                }
+            }
+            trace!("message");
+        }
+    }
+
+    /// Starts or cancels the countdown to removing the game, depending on whether it is empty or finished.
+    ///
+    /// # Returns
+    ///
+    /// When the game has to be removed, or `None` if it should stay.
+    fn update_teardown(
+        &mut self,
+        players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>,
+        delay: Duration
+    ) -> Option<Instant> {
+        if self.finished || self.are_all_players_bots(players) {
+            if self.teardown_at.is_none() {
+                debug!("Game {} will be removed in {:?}", self.id, delay);
             }
-            println!("message");
+            self.teardown_at = Some(self.teardown_at.unwrap_or_else(|| Instant::now() + delay));
+        } else {
+            self.teardown_at = None;
+        }
+        self.teardown_at
+    }
+
+    /// Removes the game from the game list. Players still in the game are sent back to the lobby and bots are stopped.
+    fn teardown(
+        game: &Arc<Mutex<Game>>,
+        players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>,
+        games: &Arc<Mutex<Vec<Arc<Mutex<Game>>>>>
+    ) {
+        let mut game_guard = game.lock().unwrap();
+        let id = game_guard.id;
+        info!("Removing game {}", id);
+        game_guard.running = false;
+
+        let player_list: Vec<i32> = game_guard.seated().collect();
+        game_guard.seats.fill(None);
+        let spectators = std::mem::take(&mut game_guard.spectators);
+        for p_id in player_list.iter().chain(&spectators) {
+            let Some(player) = get_object(players, |p| &p.lock().unwrap().id == p_id) else {
+                continue;
+            };
+            let mut player_guard = player.lock().unwrap();
+            player_guard.joined_game = None;
+            player_guard.joined_game_id = None;
+            player_guard.ready = false;
+            player_guard.spectating = false;
+            drop(player_guard);
+            // Bots stop when they leave their game
+            send_to_player(&player, &ServerEvent::LeftGame(GameJoinData::new(id)));
         }
+        drop(game_guard);
+
+        players.lock().unwrap().retain(|p| {
+            let player_guard = p.lock().unwrap();
+            !(player_guard.is_bot && player_list.contains(&player_guard.id))
+        });
+        games.lock().unwrap().retain(|g| !Arc::ptr_eq(g, game));
+
+        broadcast_players(players);
+        broadcast_games(players, games);
     }
 
     fn are_all_players_bots(&self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) -> bool {
         for player in players.lock().unwrap().iter() {
-            if !self.player_list.contains(&player.lock().unwrap().id) {
+            if !self.is_player(player.lock().unwrap().id) {
                 continue;
             }
             if !player.lock().unwrap().is_bot {
@@ -153,8 +586,8 @@ impl Game {
     }
 
     fn can_start(&self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) -> bool {
-        for p_id in &self.player_list {
-            let player = get_object(&players, |p| { &p.lock().unwrap().id == p_id });
+        for p_id in self.seated() {
+            let player = get_object(players, |p| { p.lock().unwrap().id == p_id });
             if !player.expect("This should never happen").lock().unwrap().ready {
                 return false;
             }
@@ -163,88 +596,281 @@ impl Game {
     }
     fn start(&mut self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
         self.running = true;
+        self.current_seat = self.first_mover(players);
         // Make sure to send current_state first to avoid breaking client
-        self.broadcast(&MessageEvent::new("current_state", self.grid.clone()), players);
-        self.broadcast_turn(players);
+        self.broadcast(&ServerEvent::CurrentState(self.grid.clone()), players);
+        self.begin_turn(players);
     }
-    fn handle_player_join(&mut self, self_arc: &Arc<Mutex<Self>>, player: &Arc<Mutex<Player>>) {
+    /// Seats the player in the lowest empty seat.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the player already has a seat or all seats are taken.
+    fn handle_player_join(
+        &mut self,
+        self_arc: &Arc<Mutex<Self>>,
+        player: &Arc<Mutex<Player>>
+    ) -> bool {
         let mut player_guard = player.lock().unwrap();
+        if self.is_player(player_guard.id) {
+            debug!("Player {} is already seated in game {}.", player_guard.id, self.id);
+            return false;
+        }
+        let Some(seat) = self.seats.iter().position(|s| s.is_none()) else {
+            warn!("No free seat for player {} in game {}.", player_guard.id, self.id);
+            return false;
+        };
         player_guard.joined_game = Some(self_arc.clone());
         player_guard.joined_game_id = Some(self.id);
-        self.player_list.push(player_guard.id);
+        self.seats[seat] = Some(player_guard.id);
         drop(player_guard);
         if self.running {
             self.send_current_state(player);
         }
+        true
+    }
+    fn handle_spectator_join(
+        &mut self,
+        self_arc: &Arc<Mutex<Self>>,
+        player: &Arc<Mutex<Player>>
+    ) {
+        let mut player_guard = player.lock().unwrap();
+        player_guard.joined_game = Some(self_arc.clone());
+        player_guard.joined_game_id = Some(self.id);
+        player_guard.spectating = true;
+        self.spectators.push(player_guard.id);
+        drop(player_guard);
+        self.send_progress(player);
+    }
+
+    /// Tells a player whose session was resumed which game they are in and where it stands.
+    fn handle_rejoin(&self, player: &Arc<Mutex<Player>>) {
+        let data = GameJoinData::new(self.id);
+        let event = if player.lock().unwrap().spectating {
+            ServerEvent::SpectatingGame(data)
+        } else {
+            ServerEvent::JoinedGame(data)
+        };
+        send_to_player(player, &event);
+        self.send_progress(player);
+    }
+
+    /// Sends the grid, the turn and the pause state of a running game to a player who just got into it.
+    fn send_progress(&self, player: &Arc<Mutex<Player>>) {
+        if !self.running {
+            return;
+        }
+        self.send_current_state(player);
+        send_to_player(player, &self.turn_event());
+        if self.is_paused() || !self.pause_requests.is_empty() {
+            send_to_player(player, &self.pause_event());
+        }
     }
     fn broadcast_current_state(&self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
         for player in players.lock().unwrap().iter() {
-            if !self.player_list.contains(&player.lock().unwrap().id) {
+            let id = player.lock().unwrap().id;
+            if !self.is_player(id) && !self.spectators.contains(&id) {
                 continue;
             }
             self.send_current_state(player);
         }
     }
     fn send_current_state(&self, player: &Arc<Mutex<Player>>) {
-        Self::send_to_player_arc(player, &MessageEvent::new("current_state", self.grid.clone()));
+        send_to_player(player, &ServerEvent::CurrentState(self.grid.clone()));
     }
     pub fn ready_toggle(&self, player: &Arc<Mutex<Player>>) -> Status {
+        if player.lock().unwrap().spectating {
+            return Status::new("error", "Only players can get ready.");
+        }
+        if self.finished {
+            return Status::new("error", "Game is finished.");
+        }
         if self.running {
             return Status::new("error", "Game is already running.");
         }
         let mut player_guard = player.lock().unwrap();
         player_guard.ready = !player_guard.ready;
         if player_guard.ready {
-            self.tx.send(InternalMessage::new_ready(player.clone())).unwrap();
+            self.send_internal(InternalMessage::new_ready(player.clone()));
         }
         Status::new("ok", "")
     }
-    fn send_to_player(player: &Player, msg: &MessageEvent) {
-        player.tx.send(msg.clone()).unwrap();
-    }
-    fn send_to_player_arc(player: &Arc<Mutex<Player>>, msg: &MessageEvent) {
-        let player_guard = player.lock().unwrap();
-        Self::send_to_player(&player_guard, msg);
-    }
     fn send_to_player_id(
         &self,
         player_id: i32,
-        msg: &MessageEvent,
+        msg: &ServerEvent,
         players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>
     ) {
         let player = get_object(players, |p| { p.lock().unwrap().id == player_id });
         if player.is_none() {
             return;
         }
-        Self::send_to_player_arc(&player.unwrap(), msg);
+        send_to_player(&player.unwrap(), msg);
     }
 
-    fn broadcast(&self, msg: &MessageEvent, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
-        for player in &self.player_list {
-            self.send_to_player_id(*player, msg, players);
+    /// Sends the event to all players and spectators of the game.
+    fn broadcast(&self, msg: &ServerEvent, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
+        for player in self.seated().chain(self.spectators.iter().copied()) {
+            self.send_to_player_id(player, msg, players);
         }
     }
 
     fn broadcast_turn(&self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
-        self.broadcast(
-            &MessageEvent::new(
-                "turn",
-                serde_json
-                    ::to_string(
-                        if self.current_turn < self.player_list.len() {
-                            &self.player_list[self.current_turn]
-                        } else {
-                            &0
-                        }
-                    )
-                    .unwrap()
+        self.broadcast(&self.turn_event(), players);
+    }
+
+    fn turn_event(&self) -> ServerEvent {
+        // The clocks stand still while the game is paused
+        let now = self.paused_at.unwrap_or_else(Instant::now);
+        let current_player = self.current_player();
+        let clocks = self
+            .seated()
+            .filter_map(|id| {
+                let mut remaining = self.bank(id)?;
+                if current_player == Some(id) {
+                    remaining = remaining.saturating_sub(now - self.turn_started);
+                }
+                Some(ClockData {
+                    player_id: id,
+                    remaining_ms: remaining.as_millis() as u64,
+                })
+            })
+            .collect();
+
+        ServerEvent::Turn(TurnData {
+            player_id: current_player.unwrap_or(0),
+            time_left_ms: self.turn_deadline.map(
+                |d| d.saturating_duration_since(now).as_millis() as u64
             ),
+            clocks,
+        })
+    }
+
+    fn current_player(&self) -> Option<i32> {
+        self.seats.get(self.current_seat).copied().flatten()
+    }
+
+    /// Finds the first seat from `start` on, going around the seats, that holds a connected player.
+    ///
+    /// # Returns
+    ///
+    /// The first occupied seat from `start` on if all seated players are disconnected, or `0` if no seat is occupied.
+    fn connected_seat(&self, start: usize, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) -> usize {
+        let count = self.seats.len();
+        let occupied = (0..count)
+            .map(|offset| (start + offset) % count)
+            .filter(|seat| self.seats[*seat].is_some());
+        let connected = occupied.clone().find(|seat| {
+            let id = self.seats[*seat].unwrap();
+            get_object(players, |p| p.lock().unwrap().id == id).is_some_and(
+                |p| p.lock().unwrap().connected
+            )
+        });
+        connected.or_else(|| occupied.clone().next()).unwrap_or(0)
+    }
+
+    /// Returns the remaining time bank of the player, or `None` if the game has no chess clock.
+    fn bank(&self, player_id: i32) -> Option<Duration> {
+        let initial = Duration::from_millis(self.time_control?.initial_ms?);
+        Some(self.clocks.get(&player_id).copied().unwrap_or(initial))
+    }
+
+    /// Returns when the current turn times out, or `None` if it can't time out.
+    fn turn_deadline(&self) -> Option<Instant> {
+        if !self.running || self.is_paused() {
+            return None;
+        }
+        self.turn_deadline
+    }
+
+    /// Starts the timer of the current player's turn and announces the turn.
+    fn begin_turn(&mut self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
+        // A turn passed on while paused starts when the pause did, resuming then moves it by the paused time
+        self.begin_turn_at(self.paused_at.unwrap_or_else(Instant::now), players);
+    }
+
+    /// Starts the current player's turn as if it had started at `started`.
+    fn begin_turn_at(&mut self, started: Instant, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
+        self.turn_started = started;
+        let move_time = self.time_control
+            .and_then(|tc| tc.move_time_ms)
+            .map(Duration::from_millis);
+        let bank = self.current_player().and_then(|id| self.bank(id));
+        let limit = match (move_time, bank) {
+            (Some(move_time), Some(bank)) => Some(move_time.min(bank)),
+            (move_time, bank) => move_time.or(bank),
+        };
+        self.turn_deadline = limit.map(|limit| self.turn_started + limit);
+
+        self.broadcast_turn(players);
+    }
+
+    /// Charges the time taken by the current player to its chess clock and adds the increment.
+    fn end_turn(&mut self) {
+        let (Some(id), Some(time_control)) = (self.current_player(), self.time_control) else {
+            return;
+        };
+        if let Some(bank) = self.bank(id) {
+            let remaining = bank.saturating_sub(self.turn_started.elapsed());
+            self.clocks.insert(id, remaining + Duration::from_millis(time_control.increment_ms));
+        }
+    }
+
+    /// Skips or removes the current player after it ran out of time. A player whose chess clock ran out is always
+    /// removed, it would otherwise time out right away on each of its turns.
+    fn handle_turn_timeout(
+        game: &Arc<Mutex<Game>>,
+        players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>,
+        games: &Arc<Mutex<Vec<Arc<Mutex<Game>>>>>
+    ) {
+        let mut game_guard = game.lock().unwrap();
+        let Some(id) = game_guard.current_player() else {
+            // Nobody is left to time out
+            game_guard.turn_deadline = None;
+            return;
+        };
+        let clock_ran_out = game_guard
+            .bank(id)
+            .is_some_and(|bank| bank <= game_guard.turn_started.elapsed());
+        let action = if clock_ran_out {
+            TimeoutAction::Forfeit
+        } else {
+            game_guard.time_control.map(|tc| tc.on_timeout).unwrap_or_default()
+        };
+        info!("Player {} ran out of time in game {}", id, game_guard.id);
+
+        game_guard.end_turn();
+        game_guard.broadcast(
+            &ServerEvent::TurnTimeout(TurnTimeoutData { player_id: id, action }),
             players
         );
+
+        let player = get_object(players, |p| p.lock().unwrap().id == id);
+        match (action, player) {
+            (TimeoutAction::Forfeit, Some(player)) => {
+                game_guard.remove_player(&player, players);
+                drop(game_guard);
+                Self::forget_bot(&player, players);
+                broadcast_players(players);
+                broadcast_games(players, games);
+            }
+            _ => {
+                game_guard.next_turn(false, players);
+            }
+        }
     }
 
     fn broadcast_move(&self, m: &PlayerMove, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
-        self.broadcast(&MessageEvent::new("new_move", m), players);
+        self.broadcast(&ServerEvent::NewMove(m.clone()), players);
+    }
+
+    /// Drops a bot that was removed from the game from the player list.
+    ///
+    /// The bot's thread stops by itself when it receives left_game.
+    fn forget_bot(player: &Arc<Mutex<Player>>, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
+        if player.lock().unwrap().is_bot {
+            players.lock().unwrap().retain(|p| !Arc::ptr_eq(p, player));
+        }
     }
 
     fn remove_player(
@@ -253,109 +879,1475 @@ impl Game {
         players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>
     ) {
         let id = player.lock().unwrap().id;
-        let index_option = self.player_list.iter().position(|p| { p == &id });
-        if index_option.is_none() {
-            println!("Player {} not found in game {}.", player.lock().unwrap().id, self.id);
+        if let Some(index) = self.spectators.iter().position(|p| p == &id) {
+            self.spectators.remove(index);
+            let mut player_guard = player.lock().unwrap();
+            player_guard.joined_game = None;
+            player_guard.joined_game_id = None;
+            player_guard.spectating = false;
+            drop(player_guard);
+            if id == self.creator {
+                self.hand_over_creator(players);
+            }
+            send_to_player(player, &ServerEvent::LeftGame(GameJoinData::new(self.id)));
             return;
         }
 
-        let index = index_option.unwrap();
-        if index < self.current_turn {
-            self.current_turn -= 1;
-        }
+        let Some(seat) = self.seat_of(id) else {
+            warn!("Player {} not found in game {}.", id, self.id);
+            return;
+        };
 
-        player.lock().unwrap().joined_game = None;
-        player.lock().unwrap().joined_game_id = None;
+        let was_current = seat == self.current_seat;
+        self.pause_requests.retain(|p| *p != id);
+        // The players asked for their consent changed
+        self.undo_request = None;
 
-        self.player_list.remove(index);
-        self.broadcast_turn(players);
-    }
+        let mut player_guard = player.lock().unwrap();
+        player_guard.joined_game = None;
+        player_guard.joined_game_id = None;
+        player_guard.ready = false;
+        drop(player_guard);
 
-    fn next_turn(&mut self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
-        if self.are_all_players_bots(players) {
-            println!("All players are bots!");
-            self.running = false;
-            return;
+        self.seats[seat] = None;
+        if was_current {
+            self.current_seat = self.connected_seat(seat + 1, players);
+        }
+        if id == self.creator {
+            self.hand_over_creator(players);
         }
 
-        self.current_turn += 1;
-        if self.current_turn >= self.player_list.len() {
-            self.current_turn = 0;
+        send_to_player(player, &ServerEvent::LeftGame(GameJoinData::new(self.id)));
+        if was_current {
+            self.begin_turn(players);
+        } else {
+            self.broadcast_turn(players);
         }
-        self.broadcast_turn(players);
     }
 
-    pub fn join_player(&self, player: &Arc<Mutex<Player>>) -> bool {
+    fn handle_pause_request(
+        &mut self,
+        player: &Arc<Mutex<Player>>,
+        players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>
+    ) {
+        let id = player.lock().unwrap().id;
         if
-            (self.running && !self.hotjoin) ||
-            self.player_list.len() >= self.player_limit ||
-            self.player_list.contains(&player.lock().unwrap().id)
+            !self.running ||
+            self.is_paused() ||
+            !self.is_player(id) ||
+            self.pause_requests.contains(&id)
         {
-            return false;
+            return;
         }
+        self.pause_requests.push(id);
 
-        self.tx.send(InternalMessage::new_join(player.clone())).unwrap();
-        true
-    }
-    pub fn join_player_forced(&self, player: &Arc<Mutex<Player>>) {
-        self.tx.send(InternalMessage::new_join(player.clone())).unwrap();
+        // Bots don't need to agree
+        let everyone_agrees = self.seated().all(|p_id| {
+            self.pause_requests.contains(&p_id) ||
+                get_object(players, |p| p.lock().unwrap().id == p_id).is_some_and(
+                    |p| p.lock().unwrap().is_bot
+                )
+        });
+        if everyone_agrees || (self.casual && id == self.creator) {
+            info!("Game {} is paused", self.id);
+            self.pause_requests.clear();
+            self.paused_at = Some(Instant::now());
+        }
+        self.broadcast_pause(players);
     }
 
-    /// Returns true if the move is allowed and successful
-    pub fn add_move(&self, player: &Arc<Mutex<Player>>, pos: Size) -> bool {
-        if
-            !self.running ||
-            !self.grid.is_valid_move(&pos) ||
-            player.lock().unwrap().id != self.player_list[self.current_turn] // This was observed go out of bounds if player leaves, unable to reproduce again
-        {
-            return false;
+    fn handle_resume(
+        &mut self,
+        player: &Arc<Mutex<Player>>,
+        players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>
+    ) {
+        if !self.is_player(player.lock().unwrap().id) {
+            return;
         }
+        let Some(paused_at) = self.paused_at.take() else {
+            // Withdraws the requests for a pause that hasn't started yet
+            if !self.pause_requests.is_empty() {
+                self.pause_requests.clear();
+                self.broadcast_pause(players);
+            }
+            return;
+        };
 
-        self.tx.send(InternalMessage::new_move(player.clone(), pos)).unwrap();
-        true
-    }
-    pub fn leave_player(&self, player: &Arc<Mutex<Player>>) {
-        self.tx.send(InternalMessage::new_leave(player.clone())).unwrap();
+        // The time spent paused isn't charged to the player whose turn it is
+        let paused_for = paused_at.elapsed();
+        self.turn_started += paused_for;
+        self.turn_deadline = self.turn_deadline.map(|d| d + paused_for);
+        info!("Game {} is resumed", self.id);
+        self.broadcast_pause(players);
+        // Also makes a bot whose turn it is think about its move again
+        self.broadcast_turn(players);
     }
-    pub fn add_bot(&self, player: &Arc<Mutex<Player>>, bot_type: String) -> bool {
-        if player.lock().unwrap().id != self.creator {
-            return false;
-        }
 
-        self.tx.send(InternalMessage::new_add_bot(player.clone(), bot_type)).unwrap();
+    fn pause_event(&self) -> ServerEvent {
+        ServerEvent::Paused(PauseData {
+            paused: self.is_paused(),
+            requested_by: self.pause_requests.clone(),
+        })
+    }
 
-        true
+    fn broadcast_pause(&self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
+        self.broadcast(&self.pause_event(), players);
     }
-    pub fn request_current_state(&self, player: &Arc<Mutex<Player>>) {
-        self.tx.send(InternalMessage::new_current_state(player.clone())).unwrap();
+
+    fn handle_undo_request(
+        &mut self,
+        player: &Arc<Mutex<Player>>,
+        players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>
+    ) {
+        let id = player.lock().unwrap().id;
+        if !self.running || self.is_paused() || self.undo_request.is_some() || !self.is_player(id) {
+            return;
+        }
+
+        let waiting_for = self
+            .seated()
+            .filter(|p_id| {
+                *p_id != id &&
+                    get_object(players, |p| p.lock().unwrap().id == *p_id).is_some_and(
+                        |p| !p.lock().unwrap().is_bot
+                    )
+            })
+            .collect::<Vec<i32>>();
+        info!("Player {} asks to undo the last move in game {}", id, self.id);
+        if !waiting_for.is_empty() {
+            self.broadcast(&ServerEvent::UndoRequested(UndoData { player_id: id }), players);
+        }
+        self.undo_request = Some(UndoRequest { player_id: id, waiting_for });
+        self.try_undo(players);
     }
-}
 
-#[test]
-fn player_join() {
-    let players_all: Arc<Mutex<Vec<Arc<Mutex<Player>>>>> = Arc::new(Mutex::new(Vec::new()));
-    let (tx, _rx) = mpsc::channel();
-    let player = Arc::new(Mutex::new(Player::new(0, tx)));
-    players_all.lock().unwrap().push(player.clone());
-    let game_id_counter = Arc::new(Mutex::new(0));
-    let game_parameters = GameCreationData::new(Size::new(5, 5), true, 10, 4);
-    let player_id_counter = Arc::new(Mutex::new(0));
-    let game = Game::new(
-        &game_parameters,
-        &game_id_counter,
-        &player.clone(),
-        &players_all,
-        &player_id_counter
-    );
+    fn handle_undo_response(
+        &mut self,
+        player: &Arc<Mutex<Player>>,
+        accept: bool,
+        players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>
+    ) {
+        let id = player.lock().unwrap().id;
+        let Some(request) = &mut self.undo_request else {
+            return;
+        };
+        // Answers that raced with a pause are ignored, the request can be answered again after resuming
+        if !request.waiting_for.contains(&id) || self.paused_at.is_some() {
+            return;
+        }
 
-    let mut players: Vec<Arc<Mutex<Player>>> = Vec::new();
-    for i in 1..10 {
-        let (tx, _rx) = mpsc::channel();
-        let p = Arc::new(Mutex::new(Player::new(i, tx)));
-        players.push(p.clone());
-        players_all.lock().unwrap().push(p);
-        game.lock().unwrap().join_player(&players.last().unwrap());
+        if !accept {
+            debug!("Player {} declined the undo request of player {}", id, request.player_id);
+            self.undo_request = None;
+            self.broadcast(&ServerEvent::UndoDeclined(UndoData { player_id: id }), players);
+            return;
+        }
+        request.waiting_for.retain(|p| *p != id);
+        self.try_undo(players);
     }
 
-    // TODO: finish test
+    /// Takes back the last move once every player agreed to the pending undo request.
+    ///
+    /// The winning line completed by the move and the point scored with it are taken back too, and the turn goes back
+    /// to the player that made the move.
+    fn try_undo(&mut self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
+        match &self.undo_request {
+            Some(request) if request.waiting_for.is_empty() => {
+                self.undo_request = None;
+            }
+            _ => {
+                return;
+            }
+        }
+        if !self.running || self.is_paused() {
+            return;
+        }
+        let Some((m, won)) = self.grid.undo() else {
+            return;
+        };
+        info!("Undoing the move of player {} in game {}", m.player, self.id);
+
+        if won {
+            for scores in [&mut self.scores, &mut self.round_scores] {
+                if let Some(score) = scores.get_mut(&m.player) {
+                    *score = score.saturating_sub(1);
+                }
+            }
+            if let Some(player) = get_object(players, |p| p.lock().unwrap().id == m.player) {
+                let mut player_guard = player.lock().unwrap();
+                player_guard.score = player_guard.score.saturating_sub(1);
+            }
+            broadcast_players(players);
+        }
+
+        if let Some(seat) = self.seat_of(m.player) {
+            self.current_seat = seat;
+        }
+        self.broadcast_current_state(players);
+        // The mover gets back the clocks it had, minus the time it took before making the move
+        match self.clock_history.pop() {
+            Some((clocks, used)) => {
+                self.clocks = clocks;
+                let started = Instant::now().checked_sub(used).unwrap_or_else(Instant::now);
+                self.begin_turn_at(started, players);
+            }
+            None => self.begin_turn(players),
+        }
+    }
+
+    /// Makes the human player in the lowest seat the new creator of the game.
+    ///
+    /// The creator stays unchanged if only bots are left.
+    fn hand_over_creator(&mut self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
+        let new_creator = self.seated().find(|id| {
+            get_object(players, |p| p.lock().unwrap().id == *id).is_some_and(
+                |p| !p.lock().unwrap().is_bot
+            )
+        });
+        if let Some(new_creator) = new_creator {
+            info!("Player {} is the new creator of game {}", new_creator, self.id);
+            self.creator = new_creator;
+        }
+    }
+
+    /// Returns the player with the most points in the current round, or `None` if no single player leads.
+    fn round_leader(&self) -> Option<i32> {
+        let best = self.round_scores.values().copied().max().filter(|s| *s > 0)?;
+        let mut leaders = self.round_scores.iter().filter(|(_, s)| **s == best);
+        match (leaders.next(), leaders.next()) {
+            (Some((id, _)), None) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Counts the round as played and tells everyone in the game how it ended.
+    fn end_round(
+        &mut self,
+        reason: RoundOverReason,
+        winner: Option<i32>,
+        line: Vec<Size>,
+        players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>
+    ) {
+        self.rounds_played += 1;
+        debug!("Round {} of game {} is over: {:?}", self.rounds_played, self.id, reason);
+        let scores = self
+            .seated()
+            .map(|id| RoundScore {
+                player_id: id,
+                score: self.round_scores.get(&id).copied().unwrap_or(0),
+            })
+            .collect();
+        let data = RoundOverData {
+            round: self.rounds_played,
+            reason,
+            winner,
+            line,
+            scores,
+        };
+        self.broadcast(&ServerEvent::RoundOver(data.clone()), players);
+        self.previous_round = Some(data);
+    }
+
+    fn is_target_reached(&self) -> bool {
+        match self.match_target {
+            None => false,
+            Some(MatchTarget::Rounds(rounds)) => self.rounds_played >= rounds,
+            Some(MatchTarget::Points(points)) => self.scores.values().any(|s| *s >= points),
+        }
+    }
+
+    /// Returns the players of the game sorted by the points they scored in it.
+    fn standings(&self) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = self
+            .seated()
+            .map(|id| Standing {
+                player_id: id,
+                score: self.scores.get(&id).copied().unwrap_or(0),
+                rank: 0,
+            })
+            .collect();
+        standings.sort_by_key(|s| Reverse(s.score));
+
+        for i in 0..standings.len() {
+            standings[i].rank = if i > 0 && standings[i].score == standings[i - 1].score {
+                standings[i - 1].rank
+            } else {
+                i + 1
+            };
+        }
+        standings
+    }
+
+    /// Ends the match and sends the final standings to everyone in the game.
+    fn finish(&mut self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
+        info!("Game {} finished after {} rounds", self.id, self.rounds_played);
+        self.running = false;
+        self.finished = true;
+
+        for p_id in self.seated() {
+            if let Some(player) = get_object(players, |p| p.lock().unwrap().id == p_id) {
+                player.lock().unwrap().ready = false;
+            }
+        }
+
+        self.broadcast(
+            &ServerEvent::GameOver(GameOverData {
+                game_id: self.id,
+                rounds_played: self.rounds_played,
+                standings: self.standings(),
+            }),
+            players
+        );
+    }
+
+    /// Passes the turn to the next seated player that is connected, or to the player making the first move if a new
+    /// round starts.
+    fn next_turn(&mut self, new_round: bool, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
+        if self.are_all_players_bots(players) {
+            info!("All players are bots!");
+            self.running = false;
+            return;
+        }
+
+        if new_round {
+            self.current_seat = self.first_mover(players);
+        } else {
+            self.current_seat = self.connected_seat(self.current_seat + 1, players);
+        }
+        self.begin_turn(players);
+    }
+
+    /// Passes the turn on if the player to move lost its connection, so the others don't have to wait for it.
+    fn handle_disconnect(
+        &mut self,
+        player: &Arc<Mutex<Player>>,
+        players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>
+    ) {
+        let (id, connected) = {
+            let player_guard = player.lock().unwrap();
+            (player_guard.id, player_guard.connected)
+        };
+        // The player may have reconnected while the message was queued
+        if !self.running || connected || self.current_player() != Some(id) {
+            return;
+        }
+        let next = self.connected_seat(self.current_seat + 1, players);
+        if next == self.current_seat {
+            return;
+        }
+        info!("Player {} disconnected on its turn in game {}", id, self.id);
+        self.end_turn();
+        self.current_seat = next;
+        self.begin_turn(players);
+    }
+
+    /// Returns the seat of the player making the first move of the round, according to the turn order of the game.
+    /// If that player is disconnected, the next connected player starts instead.
+    fn first_mover(&self, players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) -> usize {
+        let occupied: Vec<usize> = (0..self.seats.len()).filter(|s| self.seats[*s].is_some()).collect();
+        if occupied.is_empty() {
+            return 0;
+        }
+        let rotated = occupied[(self.rounds_played as usize) % occupied.len()];
+        // Players that left since the previous round are skipped
+        let previous_winner = self.previous_round.as_ref().and_then(|r| r.winner);
+        let previous_loser = previous_winner.and_then(|_| {
+            self.previous_round
+                .as_ref()?
+                .scores.iter()
+                .filter(|s| self.is_player(s.player_id))
+                .min_by_key(|s| s.score)
+                .map(|s| s.player_id)
+        });
+
+        let seat = match self.turn_order {
+            TurnOrder::Fixed => occupied[0],
+            TurnOrder::Random => occupied[rand::thread_rng().gen_range(0..occupied.len())],
+            TurnOrder::Rotate => rotated,
+            TurnOrder::LoserStarts =>
+                previous_loser.and_then(|id| self.seat_of(id)).unwrap_or(rotated),
+            TurnOrder::WinnerStarts =>
+                previous_winner.and_then(|id| self.seat_of(id)).unwrap_or(rotated),
+        };
+        self.connected_seat(seat, players)
+    }
+
+    pub fn join_player(&self, player: &Arc<Mutex<Player>>) -> bool {
+        if
+            self.finished ||
+            self.is_banned(player.lock().unwrap().id) ||
+            (self.running && !self.hotjoin) ||
+            self.seats.iter().all(|s| s.is_some()) ||
+            self.is_player(player.lock().unwrap().id)
+        {
+            return false;
+        }
+
+        self.send_internal(InternalMessage::new_join(player.clone()))
+    }
+    /// Returns true if the player is allowed to watch the game.
+    pub fn spectate_player(&self, player: &Arc<Mutex<Player>>) -> bool {
+        let id = player.lock().unwrap().id;
+        if
+            self.finished ||
+            self.is_banned(id) ||
+            self.is_player(id) ||
+            self.spectators.contains(&id)
+        {
+            return false;
+        }
+
+        self.send_internal(InternalMessage::new_spectate(player.clone()))
+    }
+    pub fn is_banned(&self, player_id: i32) -> bool {
+        self.banned.contains(&player_id)
+    }
+    /// Removes a player or spectator from the game on behalf of the creator.
+    ///
+    /// # Arguments
+    ///
+    /// * `player`: The player asking for the removal.
+    /// * `target`: The player to remove.
+    /// * `ban`: Whether the removed player is prevented from joining again.
+    pub fn kick_player(
+        &self,
+        player: &Arc<Mutex<Player>>,
+        target: &Arc<Mutex<Player>>,
+        ban: bool
+    ) -> Status {
+        let id = player.lock().unwrap().id;
+        if !self.is_seated_creator(id) {
+            return Status::new("error", "Only the creator can remove players.");
+        }
+        let target_id = target.lock().unwrap().id;
+        if target_id == id {
+            return Status::new("error", "You can't remove yourself, leave the game instead.");
+        }
+        if !self.is_player(target_id) && !self.spectators.contains(&target_id) {
+            return Status::new("error", "Player is not in this game.");
+        }
+
+        let msg = if ban {
+            InternalMessage::new_ban(target.clone())
+        } else {
+            InternalMessage::new_kick(target.clone())
+        };
+        if !self.send_internal(msg) {
+            return Status::new("error", "Game does not exist.");
+        }
+        Status::new("ok", "")
+    }
+    pub fn join_player_forced(&self, player: &Arc<Mutex<Player>>) {
+        self.send_internal(InternalMessage::new_join(player.clone()));
+    }
+
+    /// Returns true if the move is allowed and successful
+    pub fn add_move(&self, player: &Arc<Mutex<Player>>, pos: Size) -> bool {
+        if
+            !self.running ||
+            self.is_paused() ||
+            !self.grid.is_valid_move(&pos) ||
+            self.current_player() != Some(player.lock().unwrap().id)
+        {
+            return false;
+        }
+
+        self.send_internal(InternalMessage::new_move(player.clone(), pos))
+    }
+    /// Changes the settings of the game before it starts and resets the ready flag of the human players.
+    pub fn update(
+        &mut self,
+        player: &Arc<Mutex<Player>>,
+        data: &GameUpdateData,
+        limits: &ServerLimits,
+        players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>
+    ) -> Status {
+        if !self.is_seated_creator(player.lock().unwrap().id) {
+            return Status::new("error", "Only the creator can change the game.");
+        }
+        if self.finished {
+            return Status::new("error", "Game is finished.");
+        }
+        if self.running {
+            return Status::new("error", "Game is already running.");
+        }
+        if data.player_limit.is_some_and(|limit| limit < self.seated().count()) {
+            return Status::new("error", "There are already more players in the game.").with_code(
+                ErrorCode::InvalidPlayerLimit
+            );
+        }
+        let parameters = GameCreationData {
+            size: data.size.unwrap_or(self.grid.size),
+            hotjoin: data.hotjoin.unwrap_or(self.hotjoin),
+            player_limit: data.player_limit.unwrap_or(self.seats.len()),
+            length_to_win: data.length_to_win.unwrap_or(self.win_length),
+            match_target: self.match_target,
+            time_control: self.time_control,
+            visibility: self.visibility.clone(),
+            round_end: self.round_end,
+            turn_order: self.turn_order,
+            casual: self.casual,
+        };
+        if let Err(status) = parameters.validate(limits) {
+            return status;
+        }
+
+        if let Some(size) = data.size {
+            if size != self.grid.size {
+                self.grid = Grid::new(size);
+                self.clock_history.clear();
+            }
+            self.width = size.x;
+            self.height = size.y;
+        }
+        if let Some(hotjoin) = data.hotjoin {
+            self.hotjoin = hotjoin;
+        }
+        if let Some(player_limit) = data.player_limit {
+            // The players move up to the lowest seats, keeping their order
+            let mut seats: Vec<Option<i32>> = self.seated().map(Some).collect();
+            seats.resize(player_limit, None);
+            self.seats = seats;
+        }
+        if let Some(length_to_win) = data.length_to_win {
+            self.win_length = length_to_win;
+        }
+        info!("Game {} was updated", self.id);
+
+        // The players have to agree to the new settings, bots never get ready again and keep agreeing
+        for p_id in self.seated() {
+            if let Some(p) = get_object(players, |p| p.lock().unwrap().id == p_id) {
+                let mut player_guard = p.lock().unwrap();
+                player_guard.ready = player_guard.is_bot;
+            }
+        }
+        self.broadcast(&ServerEvent::GameUpdated(self.info()), players);
+        Status::new("ok", "")
+    }
+
+    /// Asks the other players of the game to take back the last move.
+    pub fn request_undo(&self, player: &Arc<Mutex<Player>>) -> Status {
+        if !self.is_player(player.lock().unwrap().id) {
+            return Status::new("error", "Only players can ask for an undo.");
+        }
+        if !self.running {
+            return Status::new("error", "Game is not running.");
+        }
+        if self.is_paused() {
+            return Status::new("error", "Game is paused.");
+        }
+        if self.undo_request.is_some() {
+            return Status::new("error", "An undo has already been requested.");
+        }
+        if !self.grid.can_undo() {
+            return Status::new("error", "There is no move to undo.");
+        }
+
+        self.send_internal(InternalMessage::new_undo_request(player.clone()));
+        Status::new("ok", "")
+    }
+    pub fn respond_undo(&self, player: &Arc<Mutex<Player>>, accept: bool) -> Status {
+        let id = player.lock().unwrap().id;
+        if !self.undo_request.as_ref().is_some_and(|r| r.waiting_for.contains(&id)) {
+            return Status::new("error", "No undo request is waiting for your answer.");
+        }
+        if self.is_paused() {
+            return Status::new("error", "Game is paused.");
+        }
+
+        self.send_internal(InternalMessage::new_undo_response(player.clone(), accept));
+        Status::new("ok", "")
+    }
+    /// Asks to pause the game. The game is paused once every human player asked for it, or right away if the creator
+    /// of a casual game asks.
+    pub fn request_pause(&self, player: &Arc<Mutex<Player>>) -> Status {
+        let id = player.lock().unwrap().id;
+        if !self.is_player(id) {
+            return Status::new("error", "Only players can pause the game.");
+        }
+        if !self.running {
+            return Status::new("error", "Game is not running.");
+        }
+        if self.is_paused() {
+            return Status::new("error", "Game is already paused.");
+        }
+        if self.pause_requests.contains(&id) {
+            return Status::new("error", "You already asked for a pause.");
+        }
+
+        self.send_internal(InternalMessage::new_pause_request(player.clone()));
+        Status::new("ok", "")
+    }
+    pub fn resume(&self, player: &Arc<Mutex<Player>>) -> Status {
+        if !self.is_player(player.lock().unwrap().id) {
+            return Status::new("error", "Only players can resume the game.");
+        }
+        if !self.is_paused() && self.pause_requests.is_empty() {
+            return Status::new("error", "Game is not paused.");
+        }
+
+        self.send_internal(InternalMessage::new_resume(player.clone()));
+        Status::new("ok", "")
+    }
+    pub fn leave_player(&self, player: &Arc<Mutex<Player>>) {
+        self.send_internal(InternalMessage::new_leave(player.clone()));
+    }
+    /// Tells the game that the player's connection dropped, the player keeps its seat until its session expires.
+    pub fn disconnect_player(&self, player: &Arc<Mutex<Player>>) {
+        self.send_internal(InternalMessage::new_disconnect(player.clone()));
+    }
+    pub fn add_bot(&self, player: &Arc<Mutex<Player>>, bot_type: Option<String>) -> bool {
+        if !self.is_seated_creator(player.lock().unwrap().id) || self.seats.iter().all(|s| s.is_some()) {
+            return false;
+        }
+
+        self.send_internal(InternalMessage::new_add_bot(player.clone(), bot_type))
+    }
+    pub fn request_current_state(&self, player: &Arc<Mutex<Player>>) {
+        self.send_internal(InternalMessage::new_current_state(player.clone()));
+    }
+    pub fn rejoin(&self, player: &Arc<Mutex<Player>>) {
+        self.send_internal(InternalMessage::new_rejoin(player.clone()));
+    }
+
+    /// Passes a message to the game thread.
+    ///
+    /// Returns false if the game has already been removed and its thread stopped.
+    fn send_internal(&self, msg: InternalMessage) -> bool {
+        if self.tx.send(msg).is_err() {
+            debug!("Game {} has already been removed", self.id);
+            return false;
+        }
+        true
+    }
+}
+
+/// Generates the code players need to join an unlisted game.
+fn generate_invite_code() -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(8).map(char::from).collect()
+}
+
+/// Time after which the tests give up waiting for the game thread.
+#[cfg(test)]
+const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A game for the tests, together with the list of players it was created with.
+#[cfg(test)]
+struct TestGame {
+    game: Arc<Mutex<Game>>,
+    players: Arc<Mutex<Vec<Arc<Mutex<Player>>>>>,
+    games: Arc<Mutex<Vec<Arc<Mutex<Game>>>>>,
+}
+#[cfg(test)]
+impl TestGame {
+    /// Creates a game created by the first of `count` new players. None of them has joined it yet.
+    fn new(parameters: &GameCreationData, count: i32) -> (Self, Vec<TestPlayer>) {
+        Self::with_config(parameters, count, GameConfig::default())
+    }
+    fn with_config(
+        parameters: &GameCreationData,
+        count: i32,
+        config: GameConfig
+    ) -> (Self, Vec<TestPlayer>) {
+        let players = Arc::new(Mutex::new(Vec::new()));
+        let games = Arc::new(Mutex::new(Vec::new()));
+        let test_players: Vec<TestPlayer> = (0..count)
+            .map(|id| {
+                let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+                let player = Arc::new(Mutex::new(Player::new(id, tx)));
+                players.lock().unwrap().push(player.clone());
+                TestPlayer { player, rx }
+            })
+            .collect();
+        let game = Game::new(
+            parameters,
+            &Arc::new(Mutex::new(0)),
+            &test_players[0].player,
+            &players,
+            &games,
+            &Arc::new(Mutex::new(count)),
+            BotConfig::default(),
+            config
+        );
+        (Self { game, players, games }, test_players)
+    }
+    /// Adds a bot to the player list that has no thread, so it never moves.
+    fn idle_bot(&self, id: i32) -> TestPlayer {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let player = Arc::new(Mutex::new(Player::new_bot(id, tx)));
+        self.players.lock().unwrap().push(player.clone());
+        TestPlayer { player, rx }
+    }
+    /// Lets the players join the game and waits until they are seated.
+    fn seat(&self, players: &[TestPlayer]) {
+        for p in players {
+            assert!(self.game.lock().unwrap().join_player(&p.player));
+        }
+        self.wait_until(|game| players.iter().all(|p| game.is_player(p.id())));
+    }
+    /// Gets every player ready, which starts the game.
+    fn start(&self, players: &[TestPlayer]) {
+        for p in players {
+            assert_eq!(self.game.lock().unwrap().ready_toggle(&p.player).status, "ok");
+        }
+    }
+    /// Makes the moves in order, each once it is the moving player's turn. The turns are watched through the first
+    /// player's events.
+    fn play(&self, players: &mut [TestPlayer], moves: &[(usize, u32, u32)]) {
+        for &(index, x, y) in moves {
+            let id = players[index].id();
+            players[0].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == id));
+            assert!(self.game.lock().unwrap().add_move(&players[index].player, Size::new(x, y)));
+        }
+    }
+    /// Waits until the game thread has brought the game into the expected state.
+    fn wait_until(&self, condition: impl Fn(&Game) -> bool) {
+        let deadline = Instant::now() + TEST_TIMEOUT;
+        while !condition(&self.game.lock().unwrap()) {
+            assert!(Instant::now() < deadline, "Timed out waiting for the game");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+/// A player of a [`TestGame`] and the events sent to it.
+#[cfg(test)]
+struct TestPlayer {
+    player: Arc<Mutex<Player>>,
+    rx: tokio::sync::mpsc::UnboundedReceiver<ServerEvent>,
+}
+#[cfg(test)]
+impl TestPlayer {
+    fn id(&self) -> i32 {
+        self.player.lock().unwrap().id
+    }
+    fn recv_before(&mut self, deadline: Instant) -> ServerEvent {
+        use tokio::sync::mpsc::error::TryRecvError;
+        loop {
+            match self.rx.try_recv() {
+                Ok(event) => {
+                    return event;
+                }
+                Err(TryRecvError::Empty) => {
+                    assert!(Instant::now() < deadline, "Timed out waiting for an event");
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Err(TryRecvError::Disconnected) => panic!("The event channel was closed"),
+            }
+        }
+    }
+    /// Returns the next event sent to the player.
+    fn next_event(&mut self) -> ServerEvent {
+        self.recv_before(Instant::now() + TEST_TIMEOUT)
+    }
+    /// Skips events until `f` returns a value for one of them.
+    fn wait_for<T>(&mut self, mut f: impl FnMut(ServerEvent) -> Option<T>) -> T {
+        let deadline = Instant::now() + TEST_TIMEOUT;
+        loop {
+            if let Some(value) = f(self.recv_before(deadline)) {
+                return value;
+            }
+        }
+    }
+    /// Skips events until one matches the predicate.
+    fn wait_until(&mut self, predicate: impl Fn(&ServerEvent) -> bool) {
+        self.wait_for(|e| predicate(&e).then_some(()));
+    }
+}
+
+#[test]
+fn player_join() {
+    let players_all: Arc<Mutex<Vec<Arc<Mutex<Player>>>>> = Arc::new(Mutex::new(Vec::new()));
+    let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+    let player = Arc::new(Mutex::new(Player::new(0, tx)));
+    players_all.lock().unwrap().push(player.clone());
+    let game_id_counter = Arc::new(Mutex::new(0));
+    let game_parameters = GameCreationData::new(Size::new(5, 5), true, 10, 4);
+    let player_id_counter = Arc::new(Mutex::new(0));
+    let game = Game::new(
+        &game_parameters,
+        &game_id_counter,
+        &player.clone(),
+        &players_all,
+        &Arc::new(Mutex::new(Vec::new())),
+        &player_id_counter,
+        BotConfig::default(),
+        GameConfig::default()
+    );
+
+    let mut players: Vec<Arc<Mutex<Player>>> = Vec::new();
+    for i in 1..10 {
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let p = Arc::new(Mutex::new(Player::new(i, tx)));
+        players.push(p.clone());
+        players_all.lock().unwrap().push(p);
+        game.lock().unwrap().join_player(players.last().unwrap());
+    }
+
+    // TODO: finish test
+}
+
+#[test]
+fn creator_leaves() {
+    let (test, mut players) = TestGame::new(&GameCreationData::new(Size::new(3, 3), false, 2, 3), 2);
+    test.seat(&players);
+    test.game.lock().unwrap().leave_player(&players[0].player);
+
+    // Wait until the game thread has processed the leave
+    let left = players[0].wait_for(|e| {
+        match e {
+            ServerEvent::LeftGame(data) => Some(data),
+            _ => None,
+        }
+    });
+    assert_eq!(left.id, 0);
+
+    let info = test.game.lock().unwrap().info();
+    assert_eq!(info.player_list, vec![1]);
+    assert_eq!(info.creator, 1);
+    assert!(players[0].player.lock().unwrap().joined_game.is_none());
+}
+
+#[test]
+fn match_target_points() {
+    let mut parameters = GameCreationData::new(Size::new(3, 3), false, 2, 3);
+    parameters.match_target = Some(MatchTarget::Points(1));
+    let (test, mut players) = TestGame::new(&parameters, 2);
+    test.seat(&players);
+    test.start(&players);
+
+    // The first player completes a row
+    test.play(&mut players, &[(0, 0, 0), (1, 0, 1), (0, 1, 0), (1, 1, 1), (0, 2, 0)]);
+
+    let game_over = players[0].wait_for(|e| {
+        match e {
+            ServerEvent::GameOver(data) => Some(data),
+            _ => None,
+        }
+    });
+    assert_eq!(game_over.standings[0].player_id, 0);
+    assert_eq!(game_over.standings[0].score, 1);
+    assert_eq!(game_over.standings[0].rank, 1);
+    assert_eq!(game_over.standings[1].rank, 2);
+
+    let info = test.game.lock().unwrap().info();
+    assert!(info.finished);
+    assert!(!info.running);
+}
+
+#[test]
+fn empty_game_teardown() {
+    let config = GameConfig {
+        teardown_delay: Duration::from_millis(50),
+    };
+    let (test, mut players) = TestGame::with_config(
+        &GameCreationData::new(Size::new(3, 3), false, 3, 3),
+        1,
+        config
+    );
+    test.games.lock().unwrap().push(test.game.clone());
+    test.seat(&players);
+    test.game.lock().unwrap().join_player_forced(
+        &Arc::new(Mutex::new(Player::new_bot(1, tokio::sync::mpsc::unbounded_channel().0)))
+    );
+    test.game.lock().unwrap().leave_player(&players[0].player);
+
+    // The game is removed once the delay has passed without a human joining
+    let removed_at = Instant::now() + TEST_TIMEOUT;
+    while !test.games.lock().unwrap().is_empty() {
+        assert!(Instant::now() < removed_at, "Game was not removed");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    players[0].wait_until(|e| matches!(e, ServerEvent::LeftGame(_)));
+    // The game thread has stopped
+    assert!(!test.game.lock().unwrap().join_player(&players[0].player));
+}
+
+#[test]
+fn chess_clock_forfeit() {
+    let mut parameters = GameCreationData::new(Size::new(3, 3), false, 2, 3);
+    parameters.time_control = Some(TimeControl {
+        move_time_ms: None,
+        initial_ms: Some(100),
+        increment_ms: 0,
+        on_timeout: TimeoutAction::Forfeit,
+    });
+    let (test, mut players) = TestGame::new(&parameters, 2);
+    test.seat(&players);
+    test.start(&players);
+
+    let turn = players[0].wait_for(|e| {
+        match e {
+            ServerEvent::Turn(turn) => Some(turn),
+            _ => None,
+        }
+    });
+    assert_eq!(turn.player_id, 0);
+    assert!(turn.time_left_ms.is_some_and(|t| t <= 100));
+    assert_eq!(turn.clocks.len(), 2);
+
+    // The first player doesn't move and loses the game
+    let timeout = players[0].wait_for(|e| {
+        match e {
+            ServerEvent::TurnTimeout(timeout) => Some(timeout),
+            _ => None,
+        }
+    });
+    assert_eq!(timeout.player_id, 0);
+    assert_eq!(timeout.action, TimeoutAction::Forfeit);
+    players[0].wait_until(|e| matches!(e, ServerEvent::LeftGame(_)));
+    assert_eq!(test.game.lock().unwrap().info().player_list, vec![1]);
+}
+
+#[test]
+fn forfeiting_bot_is_removed() {
+    let mut parameters = GameCreationData::new(Size::new(3, 3), false, 2, 3);
+    parameters.time_control = Some(TimeControl {
+        move_time_ms: None,
+        initial_ms: Some(100),
+        increment_ms: 0,
+        on_timeout: TimeoutAction::Forfeit,
+    });
+    let (test, mut players) = TestGame::new(&parameters, 1);
+    let bot = test.idle_bot(1);
+    test.seat(&players);
+    test.seat(std::slice::from_ref(&bot));
+    test.start(&players);
+
+    players[0].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 0));
+    assert!(test.game.lock().unwrap().add_move(&players[0].player, Size::new(0, 0)));
+    players[0].wait_until(|e| matches!(e, ServerEvent::TurnTimeout(timeout) if timeout.player_id == 1));
+    players[0].wait_until(|e| matches!(e, ServerEvent::Players(list) if list.iter().all(|p| p.id != 1)));
+    assert!(!test.game.lock().unwrap().is_player(1));
+}
+
+#[test]
+fn exhausted_clock_is_not_skipped() {
+    let mut parameters = GameCreationData::new(Size::new(3, 3), false, 2, 3);
+    parameters.time_control = Some(TimeControl {
+        move_time_ms: None,
+        initial_ms: Some(100),
+        increment_ms: 0,
+        on_timeout: TimeoutAction::Skip,
+    });
+    let (test, mut players) = TestGame::new(&parameters, 2);
+    test.seat(&players);
+    test.start(&players);
+
+    // Both players let their clocks run out and are removed instead of being skipped forever
+    for id in [0, 1] {
+        let timeout = players[1].wait_for(|e| {
+            match e {
+                ServerEvent::TurnTimeout(timeout) => Some(timeout),
+                _ => None,
+            }
+        });
+        assert_eq!(timeout.player_id, id);
+        assert_eq!(timeout.action, TimeoutAction::Forfeit);
+        players[id as usize].wait_until(|e| matches!(e, ServerEvent::LeftGame(_)));
+    }
+    test.wait_until(|game| game.info().player_list.is_empty() && game.turn_deadline().is_none());
+}
+
+#[test]
+fn spectators_only_watch() {
+    let (test, mut players) = TestGame::new(&GameCreationData::new(Size::new(3, 3), false, 2, 3), 3);
+    let game = &test.game;
+    let creator = players[0].player.clone();
+    // The creator watches the other two players
+    test.seat(&players[1..]);
+    assert!(game.lock().unwrap().spectate_player(&creator));
+    test.wait_until(|game| game.info().spectators == 1);
+
+    assert_eq!(game.lock().unwrap().ready_toggle(&creator).status, "error");
+    assert!(!game.lock().unwrap().add_bot(&creator, None));
+    assert_eq!(game.lock().unwrap().kick_player(&creator, &players[1].player, false).status, "error");
+    let status = game
+        .lock()
+        .unwrap()
+        .update(&creator, &GameUpdateData::default(), &ServerLimits::default(), &test.players);
+    assert_eq!(status.status, "error");
+
+    test.start(&players[1..]);
+    players[0].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 1));
+    assert!(!game.lock().unwrap().add_move(&creator, Size::new(0, 0)));
+    assert!(game.lock().unwrap().add_move(&players[1].player, Size::new(0, 0)));
+
+    // A player takes over when the watching creator leaves
+    game.lock().unwrap().leave_player(&creator);
+    players[0].wait_until(|e| matches!(e, ServerEvent::LeftGame(_)));
+    assert_eq!(game.lock().unwrap().info().creator, 1);
+}
+
+#[test]
+fn one_game_at_a_time() {
+    let parameters = GameCreationData::new(Size::new(3, 3), false, 2, 3);
+    let (test, players) = TestGame::new(&parameters, 2);
+    let other = Game::new(
+        &parameters,
+        &Arc::new(Mutex::new(1)),
+        &players[1].player,
+        &test.players,
+        &test.games,
+        &Arc::new(Mutex::new(2)),
+        BotConfig::default(),
+        GameConfig::default()
+    );
+    test.games.lock().unwrap().extend([test.game.clone(), other.clone()]);
+    let player = &players[0].player;
+    let join = |id| Player::join_game(player, &GameJoinData::new(id), &test.games, &test.players);
+
+    // The second join is sent before the game thread handled the first one
+    assert_eq!(join(0).status, "ok");
+    assert_eq!(player.lock().unwrap().joined_game_id, Some(0));
+    assert_eq!(join(1).status, "error");
+    assert_eq!(Player::spectate_game(player, &GameJoinData::new(1), &test.games).status, "error");
+    test.wait_until(|game| game.is_player(0));
+    assert!(other.lock().unwrap().seated().next().is_none());
+    assert_eq!(player.lock().unwrap().joined_game_id, Some(0));
+}
+
+#[test]
+fn private_game_credentials() {
+    let new_game = |visibility: Visibility| {
+        let mut parameters = GameCreationData::new(Size::new(3, 3), false, 2, 3);
+        parameters.visibility = visibility;
+        TestGame::new(&parameters, 1).0.game
+    };
+
+    let game = new_game(Visibility::Password { password: "secret".to_string() });
+    let mut join_data = GameJoinData::new(0);
+    assert!(game.lock().unwrap().is_listed());
+    assert!(game.lock().unwrap().check_credentials(&join_data).is_err());
+    join_data.password = Some("secret".to_string());
+    assert!(game.lock().unwrap().check_credentials(&join_data).is_ok());
+
+    let game = new_game(Visibility::Unlisted);
+    let info = game.lock().unwrap().info();
+    assert!(!game.lock().unwrap().is_listed());
+    assert!(game.lock().unwrap().check_credentials(&GameJoinData::new(0)).is_err());
+    let mut join_data = GameJoinData::new(0);
+    join_data.invite_code = info.invite_code;
+    assert!(game.lock().unwrap().check_credentials(&join_data).is_ok());
+}
+
+#[test]
+fn kick_and_ban() {
+    let (test, mut players) = TestGame::new(&GameCreationData::new(Size::new(3, 3), false, 2, 3), 2);
+    test.seat(&players);
+    let game = &test.game;
+    let (creator, other) = (players[0].player.clone(), players[1].player.clone());
+
+    assert_eq!(game.lock().unwrap().kick_player(&other, &creator, false).status, "error");
+    assert_eq!(game.lock().unwrap().kick_player(&creator, &creator, false).status, "error");
+    assert_eq!(game.lock().unwrap().kick_player(&creator, &other, true).status, "ok");
+    players[1].wait_until(|e| matches!(e, ServerEvent::LeftGame(_)));
+
+    assert_eq!(game.lock().unwrap().info().player_list, vec![0]);
+    assert!(game.lock().unwrap().is_banned(1));
+    assert!(!game.lock().unwrap().join_player(&other));
+    assert!(!game.lock().unwrap().spectate_player(&other));
+}
+
+#[test]
+fn update_game_settings() {
+    let (test, mut players) = TestGame::new(&GameCreationData::new(Size::new(3, 3), false, 4, 3), 3);
+    let game = &test.game;
+    let limits = ServerLimits::default();
+    let update = |player: &Arc<Mutex<Player>>, data: &GameUpdateData| {
+        game.lock().unwrap().update(player, data, &limits, &test.players)
+    };
+    let (creator, other) = (players[0].player.clone(), players[2].player.clone());
+    test.seat(&players);
+    // The second player leaves an empty seat behind
+    game.lock().unwrap().leave_player(&players[1].player);
+    players[1].wait_until(|e| matches!(e, ServerEvent::LeftGame(_)));
+    assert_eq!(game.lock().unwrap().ready_toggle(&other).status, "ok");
+
+    let data = GameUpdateData {
+        size: Some(Size::new(5, 4)),
+        player_limit: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(update(&other, &data).status, "error");
+    let status = update(&creator, &GameUpdateData { player_limit: Some(1), ..Default::default() });
+    assert_eq!(status.code, Some(ErrorCode::InvalidPlayerLimit));
+    assert_eq!(update(&creator, &data).status, "ok");
+
+    // The players move up to the remaining seats and have to get ready again
+    let info = players[2].wait_for(|e| {
+        match e {
+            ServerEvent::GameUpdated(info) => Some(info),
+            _ => None,
+        }
+    });
+    assert_eq!((info.width, info.height), (5, 4));
+    assert_eq!(info.player_limit, 2);
+    assert_eq!(info.seats, vec![Some(0), Some(2)]);
+    assert!(!other.lock().unwrap().ready);
+
+    // Nothing can be changed once the game runs
+    for player in [&creator, &other] {
+        assert_eq!(game.lock().unwrap().ready_toggle(player).status, "ok");
+    }
+    test.wait_until(|game| game.info().running);
+    assert_eq!(update(&creator, &data).status, "error");
+
+    // A bot stays ready, so its game still starts once the humans agree to the update
+    let (test, players) = TestGame::new(&GameCreationData::new(Size::new(3, 3), false, 2, 3), 1);
+    let bot = test.idle_bot(1);
+    test.seat(&players);
+    test.seat(std::slice::from_ref(&bot));
+    let data = GameUpdateData { size: Some(Size::new(4, 4)), ..Default::default() };
+    let status = test.game.lock().unwrap().update(&players[0].player, &data, &limits, &test.players);
+    assert_eq!(status.status, "ok");
+    assert!(bot.player.lock().unwrap().ready);
+    test.start(&players);
+    test.wait_until(|game| game.info().running);
+}
+
+#[test]
+fn undo_winning_move() {
+    let (test, mut players) = TestGame::new(&GameCreationData::new(Size::new(3, 3), false, 2, 3), 2);
+    test.seat(&players);
+    test.start(&players);
+    let (first, second) = (players[0].player.clone(), players[1].player.clone());
+
+    // The first player completes a row
+    test.play(&mut players, &[(0, 0, 0), (1, 0, 1), (0, 1, 0), (1, 1, 1), (0, 2, 0)]);
+    players[0].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 1));
+    assert_eq!(first.lock().unwrap().score, 1);
+
+    assert_eq!(test.game.lock().unwrap().request_undo(&first).status, "ok");
+    players[1].wait_until(|e| matches!(e, ServerEvent::UndoRequested(_)));
+    assert_eq!(test.game.lock().unwrap().respond_undo(&first, true).status, "error");
+    assert_eq!(test.game.lock().unwrap().respond_undo(&second, true).status, "ok");
+
+    players[0].wait_until(|e| matches!(e, ServerEvent::CurrentState(_)));
+    players[0].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 0));
+    let game_guard = test.game.lock().unwrap();
+    assert!(game_guard.grid.is_empty(&Size::new(2, 0)));
+    assert_eq!(game_guard.grid.get_pos(&Size::new(0, 0)), Some(0));
+    assert_eq!(game_guard.scores.get(&0), Some(&0));
+    assert_eq!(first.lock().unwrap().score, 0);
+}
+
+#[test]
+fn undo_restores_clocks() {
+    let mut parameters = GameCreationData::new(Size::new(3, 3), false, 2, 3);
+    parameters.time_control = Some(TimeControl {
+        move_time_ms: None,
+        initial_ms: Some(60000),
+        increment_ms: 5000,
+        on_timeout: TimeoutAction::Forfeit,
+    });
+    let (test, mut players) = TestGame::new(&parameters, 2);
+    test.seat(&players);
+    test.start(&players);
+    let (first, second) = (players[0].player.clone(), players[1].player.clone());
+
+    test.play(&mut players, &[(0, 0, 0)]);
+    let turn = players[0].wait_for(|e| {
+        match e {
+            ServerEvent::Turn(turn) if turn.player_id == 1 => Some(turn),
+            _ => None,
+        }
+    });
+    assert!(turn.clocks.iter().any(|c| c.player_id == 0 && c.remaining_ms > 60000));
+
+    assert_eq!(test.game.lock().unwrap().request_undo(&first).status, "ok");
+    players[1].wait_until(|e| matches!(e, ServerEvent::UndoRequested(_)));
+    assert_eq!(test.game.lock().unwrap().respond_undo(&second, true).status, "ok");
+
+    // The increment of the undone move is taken back
+    let turn = players[0].wait_for(|e| {
+        match e {
+            ServerEvent::Turn(turn) if turn.player_id == 0 => Some(turn),
+            _ => None,
+        }
+    });
+    assert!(turn.clocks.iter().all(|c| c.remaining_ms <= 60000));
+    assert!(turn.time_left_ms.is_some_and(|t| t <= 60000));
+}
+
+#[test]
+fn first_line_ends_round() {
+    let mut parameters = GameCreationData::new(Size::new(4, 4), false, 2, 3);
+    parameters.round_end = RoundEnd::FirstLine;
+    let (test, mut players) = TestGame::new(&parameters, 2);
+    test.seat(&players);
+    test.start(&players);
+
+    // The first player completes a column
+    test.play(&mut players, &[(0, 0, 0), (1, 1, 0), (0, 0, 1), (1, 1, 1), (0, 0, 2)]);
+
+    let round_over = players[0].wait_for(|e| {
+        match e {
+            ServerEvent::RoundOver(data) => Some(data),
+            _ => None,
+        }
+    });
+    assert_eq!(round_over.round, 1);
+    assert_eq!(round_over.reason, RoundOverReason::Line);
+    assert_eq!(round_over.winner, Some(0));
+    assert_eq!(round_over.line.len(), 3);
+    assert!(round_over.line.contains(&Size::new(0, 1)));
+    assert_eq!(round_over.scores[0].score, 1);
+    assert_eq!(round_over.scores[1].score, 0);
+
+    // The next round starts on a fresh board
+    let ServerEvent::CurrentState(grid) = players[0].next_event() else {
+        panic!("Expected current_state after round_over");
+    };
+    assert!(grid.is_empty(&Size::new(0, 0)));
+}
+
+#[test]
+fn disconnect_passes_turn() {
+    let (test, mut players) = TestGame::new(&GameCreationData::new(Size::new(3, 3), false, 3, 3), 3);
+    test.seat(&players);
+    test.start(&players);
+    players[1].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 0));
+
+    // The player to move drops, the next one doesn't have to wait for it to come back
+    assert!(crate::session::disconnect(&players[0].player, 0));
+    test.game.lock().unwrap().disconnect_player(&players[0].player);
+    players[1].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 1));
+    assert!(test.game.lock().unwrap().is_player(0));
+    assert!(test.game.lock().unwrap().add_move(&players[1].player, Size::new(0, 0)));
+
+    // Its turn is skipped while it is away
+    players[1].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 2));
+    assert!(test.game.lock().unwrap().add_move(&players[2].player, Size::new(1, 0)));
+    players[1].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 1));
+}
+
+#[test]
+fn turn_order_policies() {
+    let (test, players) = TestGame::new(&GameCreationData::new(Size::new(3, 3), false, 3, 3), 3);
+    let mut game_guard = test.game.lock().unwrap();
+    game_guard.seats = vec![Some(0), Some(1), Some(2)];
+    for turn_order in [TurnOrder::Fixed, TurnOrder::Rotate, TurnOrder::WinnerStarts] {
+        game_guard.turn_order = turn_order;
+        assert_eq!(game_guard.first_mover(&test.players), 0);
+    }
+
+    // Player 1 won the first round, player 2 scored the least
+    game_guard.rounds_played = 1;
+    game_guard.previous_round = Some(RoundOverData {
+        round: 1,
+        reason: RoundOverReason::Points,
+        winner: Some(1),
+        line: Vec::new(),
+        scores: vec![
+            RoundScore { player_id: 0, score: 1 },
+            RoundScore { player_id: 1, score: 2 },
+            RoundScore { player_id: 2, score: 0 }
+        ],
+    });
+    let expected = [
+        (TurnOrder::Fixed, 0),
+        (TurnOrder::Rotate, 1),
+        (TurnOrder::WinnerStarts, 1),
+        (TurnOrder::LoserStarts, 2),
+    ];
+    for (turn_order, first_mover) in expected {
+        game_guard.turn_order = turn_order;
+        assert_eq!(game_guard.first_mover(&test.players), first_mover);
+    }
+    game_guard.turn_order = TurnOrder::Random;
+    assert!(game_guard.first_mover(&test.players) < 3);
+
+    // After a draw, winner and loser starts rotate
+    game_guard.rounds_played = 2;
+    game_guard.previous_round.as_mut().unwrap().winner = None;
+    game_guard.turn_order = TurnOrder::LoserStarts;
+    assert_eq!(game_guard.first_mover(&test.players), 2);
+    game_guard.turn_order = TurnOrder::WinnerStarts;
+    assert_eq!(game_guard.first_mover(&test.players), 2);
+
+    // A disconnected player is passed over for the next one
+    players[2].player.lock().unwrap().connected = false;
+    assert_eq!(game_guard.first_mover(&test.players), 0);
+}
+
+#[test]
+fn pause_needs_consent() {
+    let mut parameters = GameCreationData::new(Size::new(3, 3), false, 2, 3);
+    parameters.time_control = Some(TimeControl {
+        move_time_ms: Some(60000),
+        initial_ms: None,
+        increment_ms: 0,
+        on_timeout: TimeoutAction::Skip,
+    });
+    let (test, mut players) = TestGame::new(&parameters, 2);
+    test.seat(&players);
+    test.start(&players);
+    let game = &test.game;
+    let (first, second) = (players[0].player.clone(), players[1].player.clone());
+    players[0].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 0));
+
+    // The creator alone can't pause a game that isn't casual
+    assert_eq!(game.lock().unwrap().request_pause(&first).status, "ok");
+    let pause = players[0].wait_for(|e| {
+        match e {
+            ServerEvent::Paused(data) => Some(data),
+            _ => None,
+        }
+    });
+    assert!(!pause.paused);
+    assert_eq!(pause.requested_by, vec![0]);
+    assert_eq!(game.lock().unwrap().request_pause(&first).status, "error");
+
+    assert_eq!(game.lock().unwrap().request_pause(&second).status, "ok");
+    players[0].wait_until(|e| matches!(e, ServerEvent::Paused(data) if data.paused));
+    assert!(game.lock().unwrap().turn_deadline().is_none());
+    assert!(!game.lock().unwrap().add_move(&first, Size::new(0, 0)));
+
+    assert_eq!(game.lock().unwrap().resume(&second).status, "ok");
+    players[0].wait_until(|e| matches!(e, ServerEvent::Paused(data) if !data.paused));
+    let ServerEvent::Turn(turn) = players[0].next_event() else {
+        panic!("Expected turn after resuming");
+    };
+    assert_eq!(turn.player_id, 0);
+    assert!(turn.time_left_ms.is_some());
+    assert!(game.lock().unwrap().add_move(&first, Size::new(0, 0)));
+}
+
+#[test]
+fn rejoin_restores_progress() {
+    let (test, mut players) = TestGame::new(&GameCreationData::new(Size::new(3, 3), false, 2, 3), 3);
+    let game = &test.game;
+    let watcher = players[2].player.clone();
+    test.seat(&players[..2]);
+    assert!(game.lock().unwrap().spectate_player(&watcher));
+    test.start(&players[..2]);
+    players[0].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 0));
+    assert!(game.lock().unwrap().add_move(&players[0].player, Size::new(0, 0)));
+    players[0].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 1));
+    assert_eq!(game.lock().unwrap().request_pause(&players[0].player).status, "ok");
+    assert_eq!(game.lock().unwrap().request_pause(&players[1].player).status, "ok");
+    players[2].wait_until(|e| matches!(e, ServerEvent::Paused(data) if data.paused));
+    let id = game.lock().unwrap().id;
+
+    // A spectator who reconnects is told they are watching, and gets the grid, turn and pause
+    game.lock().unwrap().rejoin(&watcher);
+    players[2].wait_until(|e| matches!(e, ServerEvent::SpectatingGame(data) if data.id == id));
+    let ServerEvent::CurrentState(grid) = players[2].next_event() else {
+        panic!("Expected the grid after rejoining");
+    };
+    assert!(!grid.is_empty(&Size::new(0, 0)));
+    let ServerEvent::Turn(turn) = players[2].next_event() else {
+        panic!("Expected the turn after rejoining");
+    };
+    assert_eq!(turn.player_id, 1);
+    assert!(matches!(players[2].next_event(), ServerEvent::Paused(data) if data.paused));
+
+    game.lock().unwrap().rejoin(&players[1].player);
+    players[1].wait_until(|e| matches!(e, ServerEvent::JoinedGame(data) if data.id == id));
+}
+
+#[test]
+fn turn_passed_on_while_paused() {
+    let mut parameters = GameCreationData::new(Size::new(3, 3), false, 3, 3);
+    parameters.time_control = Some(TimeControl {
+        move_time_ms: Some(1000),
+        initial_ms: None,
+        increment_ms: 0,
+        on_timeout: TimeoutAction::Skip,
+    });
+    let (test, mut players) = TestGame::new(&parameters, 3);
+    test.seat(&players);
+    test.start(&players);
+    let game = &test.game;
+    players[1].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 0));
+    for p in &players {
+        assert_eq!(game.lock().unwrap().request_pause(&p.player).status, "ok");
+    }
+    players[1].wait_until(|e| matches!(e, ServerEvent::Paused(data) if data.paused));
+
+    // The player to move leaves some time into the pause
+    std::thread::sleep(Duration::from_millis(200));
+    game.lock().unwrap().leave_player(&players[0].player);
+    players[1].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 1));
+    assert_eq!(game.lock().unwrap().resume(&players[1].player).status, "ok");
+
+    // The next player still gets the whole move time
+    players[1].wait_until(|e| matches!(e, ServerEvent::Paused(data) if !data.paused));
+    let ServerEvent::Turn(turn) = players[1].next_event() else {
+        panic!("Expected turn after resuming");
+    };
+    assert_eq!(turn.player_id, 1);
+    assert!(turn.time_left_ms.is_some_and(|t| t > 900 && t <= 1000));
+}
+
+#[test]
+fn undo_waits_for_resume() {
+    let (test, mut players) = TestGame::new(&GameCreationData::new(Size::new(3, 3), false, 2, 3), 2);
+    test.seat(&players);
+    test.start(&players);
+    let game = &test.game;
+    let (first, second) = (players[0].player.clone(), players[1].player.clone());
+    test.play(&mut players, &[(0, 0, 0)]);
+    players[0].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 1));
+
+    assert_eq!(game.lock().unwrap().request_undo(&first).status, "ok");
+    players[1].wait_until(|e| matches!(e, ServerEvent::UndoRequested(_)));
+    assert_eq!(game.lock().unwrap().request_pause(&first).status, "ok");
+    assert_eq!(game.lock().unwrap().request_pause(&second).status, "ok");
+    players[0].wait_until(|e| matches!(e, ServerEvent::Paused(data) if data.paused));
+
+    // The request is kept during the pause and can be answered after it
+    assert_eq!(game.lock().unwrap().respond_undo(&second, true).status, "error");
+    assert_eq!(game.lock().unwrap().resume(&first).status, "ok");
+    players[0].wait_until(|e| matches!(e, ServerEvent::Paused(data) if !data.paused));
+    assert_eq!(game.lock().unwrap().respond_undo(&second, true).status, "ok");
+    players[0].wait_until(|e| matches!(e, ServerEvent::CurrentState(_)));
+    players[0].wait_until(|e| matches!(e, ServerEvent::Turn(turn) if turn.player_id == 0));
+    assert!(game.lock().unwrap().grid.is_empty(&Size::new(0, 0)));
+}
+
+#[test]
+fn seats_keep_their_order() {
+    let (test, players) = TestGame::new(&GameCreationData::new(Size::new(3, 3), true, 4, 3), 5);
+    let player = |id: usize| players[id].player.clone();
+    let game = &test.game;
+    let mut game_guard = game.lock().unwrap();
+    for id in 0..3 {
+        assert!(game_guard.handle_player_join(game, &player(id)));
+    }
+    game_guard.running = true;
+    game_guard.current_seat = 1;
+
+    // The seat of a player that leaves stays empty and the turn goes on to the next seat
+    game_guard.remove_player(&player(1), &test.players);
+    assert_eq!(game_guard.seats, vec![Some(0), None, Some(2), None]);
+    assert_eq!(game_guard.current_player(), Some(2));
+
+    // Hotjoining players fill the lowest empty seat
+    assert!(game_guard.handle_player_join(game, &player(3)));
+    assert_eq!(game_guard.seats, vec![Some(0), Some(3), Some(2), None]);
+    assert_eq!(game_guard.info().player_list, vec![0, 3, 2]);
+    assert_eq!(game_guard.info().current_turn, 2);
+
+    // Disconnected players are skipped
+    player(0).lock().unwrap().connected = false;
+    game_guard.next_turn(false, &test.players);
+    assert_eq!(game_guard.current_player(), Some(3));
+    game_guard.next_turn(false, &test.players);
+    assert_eq!(game_guard.current_player(), Some(2));
+    game_guard.next_turn(false, &test.players);
+    assert_eq!(game_guard.current_player(), Some(3));
+
+    // A disconnected player doesn't start the round either
+    game_guard.turn_order = TurnOrder::Fixed;
+    assert_eq!(game_guard.first_mover(&test.players), 1);
+
+    // A join queued twice doesn't take a second seat
+    assert!(!game_guard.handle_player_join(game, &player(3)));
+    assert_eq!(game_guard.seats, vec![Some(0), Some(3), Some(2), None]);
+
+    // Joins queued before the seats filled up are turned away
+    assert!(game_guard.handle_player_join(game, &player(1)));
+    assert!(!game_guard.handle_player_join(game, &player(4)));
 }
@@ -2,6 +2,9 @@ use serde::Serialize;
 
 use crate::{ common::Size, player_move::PlayerMove };
 
+/// Player id of the moves marking a winning line, which blocks its fields for the rest of the round.
+pub(crate) const BLOCKED_ID: i32 = -2;
+
 #[derive(Serialize, Clone, Debug)]
 pub(crate) struct Grid {
     pub size: Size,
@@ -11,17 +14,12 @@ pub(crate) struct Grid {
 impl Grid {
     pub fn new(size: Size) -> Self {
         Self {
-            size: size,
+            size,
             moves: Vec::with_capacity((size.x * size.y) as usize),
         }
     }
     pub fn get_pos(&self, pos: &Size) -> Option<i32> {
-        let index = self.get_index(pos);
-
-        if index.is_some() {
-            return Some(self.moves[index.unwrap()].player);
-        }
-        None
+        self.get_index(pos).map(|index| self.moves[index].player)
     }
     pub fn add_range(&mut self, moves: &Vec<PlayerMove>) {
         self.moves.extend_from_slice(moves.as_slice());
@@ -29,15 +27,29 @@ impl Grid {
     pub fn add(&mut self, m: PlayerMove) {
         self.moves.push(m);
     }
+    /// Removes the last move together with the winning line it completed, if any.
+    ///
+    /// # Returns
+    ///
+    /// The removed move and whether it had won, or `None` if there are no moves.
+    pub fn undo(&mut self) -> Option<(PlayerMove, bool)> {
+        let mut won = false;
+        while self.moves.last().is_some_and(|m| m.player == BLOCKED_ID) {
+            self.moves.pop();
+            won = true;
+        }
+        self.moves.pop().map(|m| (m, won))
+    }
+    /// Returns true if there is a move that [`Grid::undo`] can remove.
+    pub fn can_undo(&self) -> bool {
+        self.moves.iter().any(|m| m.player != BLOCKED_ID)
+    }
     fn get_index(&self, pos: &Size) -> Option<usize> {
         let index = self.moves
             .iter()
             .rev()
-            .position(|m| m.position == *pos);
-        if index.is_none() {
-            return None;
-        }
-        Some(self.moves.len() - index.unwrap() - 1)
+            .position(|m| m.position == *pos)?;
+        Some(self.moves.len() - index - 1)
     }
     pub fn is_empty(&self, pos: &Size) -> bool {
         self.get_pos(pos).is_none()
@@ -57,8 +69,6 @@ impl Grid {
         let mut i = 0;
         let mut moves: Vec<PlayerMove> = Vec::new();
 
-        let blocked_id = -2;
-
         // left
         while i < win_length && pos.x >= i {
             if self.get_pos(&Size::new(pos.x - i, pos.y)) != player_id {
@@ -78,10 +88,10 @@ impl Grid {
         }
         if length[0] + length[1] >= win_length {
             for j in 0..length[0] {
-                moves.push(PlayerMove::new(blocked_id, Size::new(pos.x - j, pos.y)));
+                moves.push(PlayerMove::new(BLOCKED_ID, Size::new(pos.x - j, pos.y)));
             }
             for j in 0..length[1] {
-                moves.push(PlayerMove::new(blocked_id, Size::new(pos.x + j + 1, pos.y)));
+                moves.push(PlayerMove::new(BLOCKED_ID, Size::new(pos.x + j + 1, pos.y)));
             }
             return moves;
         }
@@ -108,10 +118,10 @@ impl Grid {
         }
         if length[0] + length[1] >= win_length {
             for j in 0..length[0] {
-                moves.push(PlayerMove::new(blocked_id, Size::new(pos.x, pos.y - j)));
+                moves.push(PlayerMove::new(BLOCKED_ID, Size::new(pos.x, pos.y - j)));
             }
             for j in 0..length[1] {
-                moves.push(PlayerMove::new(blocked_id, Size::new(pos.x, pos.y + j + 1)));
+                moves.push(PlayerMove::new(BLOCKED_ID, Size::new(pos.x, pos.y + j + 1)));
             }
             return moves;
         }
@@ -138,10 +148,10 @@ impl Grid {
         }
         if length[0] + length[1] >= win_length {
             for j in 0..length[0] {
-                moves.push(PlayerMove::new(blocked_id, Size::new(pos.x - j, pos.y - j)));
+                moves.push(PlayerMove::new(BLOCKED_ID, Size::new(pos.x - j, pos.y - j)));
             }
             for j in 0..length[1] {
-                moves.push(PlayerMove::new(blocked_id, Size::new(pos.x + j + 1, pos.y + j + 1)));
+                moves.push(PlayerMove::new(BLOCKED_ID, Size::new(pos.x + j + 1, pos.y + j + 1)));
             }
             return moves;
         }
@@ -168,16 +178,48 @@ impl Grid {
         }
         if length[0] + length[1] >= win_length {
             for j in 0..length[0] {
-                moves.push(PlayerMove::new(blocked_id, Size::new(pos.x - j, pos.y + j)));
+                moves.push(PlayerMove::new(BLOCKED_ID, Size::new(pos.x - j, pos.y + j)));
             }
             for j in 0..length[1] {
-                moves.push(PlayerMove::new(blocked_id, Size::new(pos.x + j + 1, pos.y - j - 1)));
+                moves.push(PlayerMove::new(BLOCKED_ID, Size::new(pos.x + j + 1, pos.y - j - 1)));
             }
             return moves;
         }
 
         Vec::new()
     }
+    /// Returns true if a player can still complete a line of `win_length`, that is if some line of that length only
+    /// contains empty fields and fields of a single player.
+    pub fn can_complete_line(&self, win_length: u32) -> bool {
+        let (width, height, length) = (self.size.x as i64, self.size.y as i64, win_length as i64);
+        let mut fields: Vec<Option<i32>> = vec![None; (width * height) as usize];
+        for m in &self.moves {
+            fields[(m.position.y as i64 * width + m.position.x as i64) as usize] = Some(m.player);
+        }
+
+        for (dx, dy) in [(1, 0), (0, 1), (1, 1), (1, -1)] {
+            for y in 0..height {
+                for x in 0..width {
+                    let (end_x, end_y) = (x + dx * (length - 1), y + dy * (length - 1));
+                    if end_x >= width || end_y < 0 || end_y >= height {
+                        continue;
+                    }
+                    let mut owner = None;
+                    let open = (0..length).all(|i| {
+                        match fields[((y + dy * i) * width + x + dx * i) as usize] {
+                            None => true,
+                            Some(BLOCKED_ID) => false,
+                            Some(player) => *owner.get_or_insert(player) == player,
+                        }
+                    });
+                    if open {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
     pub fn get_possible_moves(&self, id: i32) -> Vec<PlayerMove> {
         let mut moves = Vec::new();
         for i in 0..self.size.x {
@@ -223,3 +265,26 @@ fn test_grid() {
     assert_eq!(grid.get_pos(&Size::new(2, 0)), Some(1001));
     assert_eq!(grid.get_pos(&Size::new(0, 2)), Some(1000));
 }
+
+#[test]
+fn test_can_complete_line() {
+    let mut grid = Grid::new(Size::new(3, 3));
+    assert!(grid.can_complete_line(3));
+    assert!(!grid.can_complete_line(4));
+
+    // Every row, column and diagonal is taken by both players
+    let moves = [(0, 0, 1), (1, 0, 2), (2, 0, 1), (1, 1, 2), (0, 1, 1), (2, 1, 2), (1, 2, 1), (0, 2, 2)];
+    for (x, y, player) in moves {
+        grid.add(PlayerMove::new(player, Size::new(x, y)));
+    }
+    assert!(!grid.can_complete_line(3));
+    // A single field is still free
+    assert!(grid.can_complete_line(1));
+
+    // A winning line blocks its fields
+    let mut grid = Grid::new(Size::new(3, 1));
+    grid.add(PlayerMove::new(BLOCKED_ID, Size::new(0, 0)));
+    assert!(grid.can_complete_line(2));
+    grid.add(PlayerMove::new(BLOCKED_ID, Size::new(1, 0)));
+    assert!(!grid.can_complete_line(2));
+}
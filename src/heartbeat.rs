@@ -0,0 +1,141 @@
+//! Detection of dead and idle connections.
+
+use std::time::{ Duration, Instant };
+
+use serde::Deserialize;
+
+use crate::config::seconds;
+
+/// Timeouts of a connection. Given in seconds in the configuration file.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct HeartbeatConfig {
+    /// Time between WebSocket pings sent by the server.
+    #[serde(deserialize_with = "seconds")]
+    pub ping_interval: Duration,
+    /// Time to wait for a pong before the connection is considered dead.
+    #[serde(deserialize_with = "seconds")]
+    pub pong_timeout: Duration,
+    /// Time without any message from the client after which it is disconnected.
+    #[serde(deserialize_with = "seconds")]
+    pub idle_timeout: Duration,
+    /// Time the client has after connecting to finish the TLS and WebSocket handshakes and send its `hello`.
+    #[serde(deserialize_with = "seconds")]
+    pub handshake_timeout: Duration,
+}
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(20),
+            pong_timeout: Duration::from_secs(10),
+            idle_timeout: Duration::from_secs(30 * 60),
+            handshake_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// What the connection has to do when [`Heartbeat::poll`] is called.
+#[derive(PartialEq, Debug)]
+pub(crate) enum HeartbeatAction {
+    Wait,
+    /// A ping has to be sent.
+    Ping,
+    /// The client did not answer the last ping in time.
+    Dead,
+    /// The client has not sent any request for too long.
+    Idle,
+}
+
+/// Tracks pings and client activity of a single connection.
+pub(crate) struct Heartbeat {
+    config: HeartbeatConfig,
+    last_ping: Instant,
+    awaiting_pong: bool,
+    last_activity: Instant,
+}
+impl Heartbeat {
+    pub fn new(config: HeartbeatConfig, now: Instant) -> Self {
+        Self {
+            config,
+            last_ping: now,
+            awaiting_pong: false,
+            last_activity: now,
+        }
+    }
+
+    /// Returns the next time [`Heartbeat::poll`] has to be called.
+    pub fn next_deadline(&self) -> Instant {
+        let ping_deadline = if self.awaiting_pong {
+            self.last_ping + self.config.pong_timeout
+        } else {
+            self.last_ping + self.config.ping_interval
+        };
+        ping_deadline.min(self.last_activity + self.config.idle_timeout)
+    }
+
+    /// Decides what has to be done at the given time. Assumes a ping is sent when [`HeartbeatAction::Ping`] is returned.
+    pub fn poll(&mut self, now: Instant) -> HeartbeatAction {
+        if now >= self.last_activity + self.config.idle_timeout {
+            return HeartbeatAction::Idle;
+        }
+        if self.awaiting_pong {
+            if now >= self.last_ping + self.config.pong_timeout {
+                return HeartbeatAction::Dead;
+            }
+            return HeartbeatAction::Wait;
+        }
+        if now >= self.last_ping + self.config.ping_interval {
+            self.last_ping = now;
+            self.awaiting_pong = true;
+            return HeartbeatAction::Ping;
+        }
+        HeartbeatAction::Wait
+    }
+
+    /// Records a pong from the client.
+    pub fn pong_received(&mut self) {
+        self.awaiting_pong = false;
+    }
+
+    /// Records a request from the client.
+    pub fn activity(&mut self, now: Instant) {
+        self.last_activity = now;
+    }
+}
+
+#[test]
+fn heartbeat() {
+    let config = HeartbeatConfig {
+        ping_interval: Duration::from_secs(10),
+        pong_timeout: Duration::from_secs(5),
+        idle_timeout: Duration::from_secs(60),
+        handshake_timeout: Duration::from_secs(5),
+    };
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut heartbeat = Heartbeat::new(config, start);
+
+    assert_eq!(heartbeat.next_deadline(), at(10));
+    assert_eq!(heartbeat.poll(at(5)), HeartbeatAction::Wait);
+    assert_eq!(heartbeat.poll(at(10)), HeartbeatAction::Ping);
+    assert_eq!(heartbeat.next_deadline(), at(15));
+    heartbeat.pong_received();
+    assert_eq!(heartbeat.next_deadline(), at(20));
+    assert_eq!(heartbeat.poll(at(20)), HeartbeatAction::Ping);
+    assert_eq!(heartbeat.poll(at(25)), HeartbeatAction::Dead);
+
+    let mut heartbeat = Heartbeat::new(
+        HeartbeatConfig {
+            ping_interval: Duration::from_secs(10),
+            pong_timeout: Duration::from_secs(5),
+            idle_timeout: Duration::from_secs(15),
+            handshake_timeout: Duration::from_secs(5),
+        },
+        start
+    );
+    heartbeat.activity(at(8));
+    assert_eq!(heartbeat.poll(at(10)), HeartbeatAction::Ping);
+    heartbeat.pong_received();
+    assert_eq!(heartbeat.next_deadline(), at(20));
+    assert_eq!(heartbeat.poll(at(23)), HeartbeatAction::Idle);
+}
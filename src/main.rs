@@ -5,322 +5,479 @@ mod net;
 mod common;
 mod player_move;
 mod bot;
+mod tls;
+mod ratelimit;
+mod heartbeat;
+mod session;
+mod config;
 
-use std::{ io, env };
-use std::net::{ TcpListener, TcpStream };
-use std::sync::{ Mutex, Arc, mpsc };
-use std::thread::Builder;
-use std::time::Duration;
-use game::Game;
-use tungstenite::accept;
+use std::net::SocketAddr;
+use std::sync::{ Mutex, Arc };
+use std::time::Instant;
+use clap::Parser;
+use futures_util::{ future, SinkExt, StreamExt };
+use game::{ Game, GameConfig };
+use log::{ debug, error, info, warn, LevelFilter };
+use tokio::io::{ AsyncRead, AsyncWrite };
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::handshake::server::{ ErrorResponse, Request, Response },
+    tungstenite::Message,
+    WebSocketStream,
+};
+use crate::bot::BotConfig;
 use crate::common::{ get_unique_id, Size };
+use crate::config::{ Cli, Config, ServerLimits };
+use crate::heartbeat::{ Heartbeat, HeartbeatAction, HeartbeatConfig };
 use crate::player::Player;
+use crate::session::SessionConfig;
+use crate::ratelimit::{ AddressRateLimiter, RateLimitConfig, RateLimitResult, RateLimiter };
 use crate::net::{
+    broadcast,
     broadcast_games,
     broadcast_players,
-    GameCreationData,
-    MessageEvent,
+    ClientMessage,
+    ClientRequest,
+    Encoding,
+    ErrorCode,
+    HelloData,
+    HelloResponse,
     PlayerImageResponse,
+    ServerEvent,
     Status,
+    MIN_PROTOCOL_VERSION,
 };
 
-/// Initializes server variables. Listens for incoming connection and creates new threads to handle connected players.
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let listen_ip = if args.len() > 1 { args[1].as_str() } else { "0.0.0.0:9001" };
-
-    let server = TcpListener::bind(listen_ip).unwrap();
-    println!("Listening on {}", listen_ip);
-
-    let player_id_counter: Arc<Mutex<i32>> = Arc::new(Mutex::new(0));
-    let players: Arc<Mutex<Vec<Arc<Mutex<Player>>>>> = Arc::new(
-        Mutex::new(Vec::<Arc<Mutex<Player>>>::new())
-    );
-    let games: Arc<Mutex<Vec<Arc<Mutex<Game>>>>> = Arc::new(
-        Mutex::new(Vec::<Arc<Mutex<Game>>>::new())
-    );
-    let game_id_counter: Arc<Mutex<u32>> = Arc::new(Mutex::new(0));
-
-    for stream in server.incoming() {
-        Builder::new()
-            .name("Player Handler Thread".to_string())
-            .spawn({
-                let players_clone = players.clone();
-                let games_clone = games.clone();
-                let game_id_counter_clone = game_id_counter.clone();
-                let player_id_counter_clone = player_id_counter.clone();
-
-                move || {
-                    handle_connection(
-                        stream.unwrap(),
-                        player_id_counter_clone,
-                        players_clone,
-                        games_clone,
-                        game_id_counter_clone
-                    );
+/// State shared by all connections.
+#[derive(Clone)]
+struct ServerState {
+    player_id_counter: Arc<Mutex<i32>>,
+    players: Arc<Mutex<Vec<Arc<Mutex<Player>>>>>,
+    games: Arc<Mutex<Vec<Arc<Mutex<Game>>>>>,
+    game_id_counter: Arc<Mutex<u32>>,
+    rate_limits: Arc<RateLimitConfig>,
+    address_limiter: Arc<AddressRateLimiter>,
+    heartbeat: Arc<HeartbeatConfig>,
+    sessions: Arc<SessionConfig>,
+    limits: Arc<ServerLimits>,
+    bots: BotConfig,
+    game_config: GameConfig,
+}
+
+/// Initializes server variables. Listens for incoming connections and spawns a task to handle each connected player.
+///
+/// Settings are read from the configuration file given with `--config` and overridden by the other command line
+/// options, see `--help`. When a certificate and key are given, connections are served over TLS (`wss://`).
+#[tokio::main]
+async fn main() {
+    let config = match Config::load(Cli::parse()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Invalid configuration: {}", err);
+            std::process::exit(1);
+        }
+    };
+    env_logger::Builder
+        ::new()
+        .filter_level(config.log_level.min(LevelFilter::Warn))
+        .filter_module(module_path!(), config.log_level)
+        .init();
+
+    let tls_acceptor = match &config.tls {
+        Some(tls) => {
+            match tls::load_acceptor(&tls.certificate, &tls.private_key) {
+                Ok(acceptor) => Some(acceptor),
+                Err(err) => {
+                    error!("Unable to load TLS certificate: {}", err);
+                    std::process::exit(1);
                 }
-            })
-            .expect("Could not create thread");
+            }
+        }
+        None => None,
+    };
+
+    let mut listeners = Vec::new();
+    for address in &config.listen {
+        match TcpListener::bind(address).await {
+            Ok(listener) => {
+                info!(
+                    "Listening on {}://{}",
+                    if tls_acceptor.is_some() { "wss" } else { "ws" },
+                    address
+                );
+                listeners.push(listener);
+            }
+            Err(err) => {
+                error!("Unable to listen on {}: {}", address, err);
+                std::process::exit(1);
+            }
+        }
     }
+
+    let state = ServerState {
+        player_id_counter: Arc::new(Mutex::new(0)),
+        players: Arc::new(Mutex::new(Vec::<Arc<Mutex<Player>>>::new())),
+        games: Arc::new(Mutex::new(Vec::<Arc<Mutex<Game>>>::new())),
+        game_id_counter: Arc::new(Mutex::new(0)),
+        rate_limits: Arc::new(config.rate_limits),
+        address_limiter: Arc::new(AddressRateLimiter::new()),
+        heartbeat: Arc::new(config.heartbeat),
+        sessions: Arc::new(config.sessions),
+        limits: Arc::new(config.limits),
+        bots: config.bots,
+        game_config: config.games,
+    };
+
+    future::join_all(
+        listeners
+            .into_iter()
+            .map(|listener| accept_connections(listener, tls_acceptor.clone(), state.clone()))
+    ).await;
 }
 
-/// Handles all communication with a player.
-fn handle_connection(
-    stream: TcpStream,
-    player_id_counter: Arc<Mutex<i32>>,
-    players: Arc<Mutex<Vec<Arc<Mutex<Player>>>>>,
-    games: Arc<Mutex<Vec<Arc<Mutex<Game>>>>>,
-    game_id_counter: Arc<Mutex<u32>>
+/// Accepts connections on a listener and spawns a task to handle each of them.
+async fn accept_connections(
+    listener: TcpListener,
+    tls_acceptor: Option<TlsAcceptor>,
+    state: ServerState
 ) {
-    let addr = get_addr(&stream);
-    println!("New connection: {}", addr);
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                warn!("Unable to accept connection: {}", err);
+                continue;
+            }
+        };
 
-    // Prevent read from blocking forever
-    stream.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        let state = state.clone();
+        let tls_acceptor = tls_acceptor.clone();
 
-    let websocket_result = accept(stream);
-    if websocket_result.is_err() {
-        println!("{} - {}", addr, websocket_result.err().unwrap());
-        return;
+        tokio::spawn(async move {
+            // The TLS handshake, the WebSocket upgrade and the hello all have to finish before this
+            let handshake_deadline = tokio::time::Instant::now() + state.heartbeat.handshake_timeout;
+            match tls_acceptor {
+                Some(acceptor) => {
+                    match tokio::time::timeout_at(handshake_deadline, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            handle_connection(stream, addr, state, handshake_deadline).await;
+                        }
+                        Ok(Err(err)) => {
+                            warn!("{} - TLS handshake failed: {}", addr, err);
+                        }
+                        Err(_) => {
+                            info!("{} - TLS handshake timed out", addr);
+                        }
+                    }
+                }
+                None => {
+                    handle_connection(stream, addr, state, handshake_deadline).await;
+                }
+            }
+        });
     }
-    let mut websocket = websocket_result.unwrap();
+}
 
-    // Create channel
-    let (tx, rx) = mpsc::channel();
+/// Handles all communication with a player.
+///
+/// Waits on both the socket and the player's outgoing queue, so queued events are sent as soon as they arrive.
+async fn handle_connection<S>(
+    stream: S,
+    addr: SocketAddr,
+    state: ServerState,
+    handshake_deadline: tokio::time::Instant
+)
+    where S: AsyncRead + AsyncWrite + Unpin
+{
+    info!("New connection: {}", addr);
 
-    // Handle ID
-    let my_id = get_unique_id(&player_id_counter);
+    // Pick the wire encoding from the subprotocols requested by the client
+    let mut encoding = Encoding::Json;
+    #[allow(clippy::result_large_err)] // The error type is dictated by tungstenite
+    let select_subprotocol = |request: &Request, mut response: Response| -> Result<
+        Response,
+        ErrorResponse
+    > {
+        let requested = request
+            .headers()
+            .get("Sec-WebSocket-Protocol")
+            .and_then(|h| h.to_str().ok())
+            .and_then(Encoding::from_subprotocols);
+        if let Some(requested) = requested {
+            encoding = requested;
+            response
+                .headers_mut()
+                .insert("Sec-WebSocket-Protocol", requested.subprotocol().parse().unwrap());
+        }
+        Ok(response)
+    };
 
-    let player_arc = Arc::new(Mutex::new(Player::new(my_id, tx)));
+    let upgrade = tokio::time::timeout_at(handshake_deadline, accept_hdr_async(stream, select_subprotocol));
+    let mut websocket = match upgrade.await {
+        Ok(Ok(websocket)) => websocket,
+        Ok(Err(err)) => {
+            warn!("{} - {}", addr, err);
+            return;
+        }
+        Err(_) => {
+            info!("{} - WebSocket upgrade timed out", addr);
+            return;
+        }
+    };
 
-    // Add new player to list
-    players.lock().unwrap().push(player_arc.clone());
+    debug!("{} - encoding: {}", addr, encoding.name());
 
-    loop {
-        // Process queue
-        let result = rx.try_recv();
-        if result.is_ok() {
-            websocket.send(result.unwrap().to_message()).unwrap();
+    let hello = tokio::time
+        ::timeout_at(handshake_deadline, handshake(&mut websocket, addr, encoding)).await
+        .unwrap_or_else(|_| {
+            info!("{} - No hello received in time", addr);
+            None
+        });
+    let Some(hello) = hello else {
+        info!("Closing connection {}", addr);
+        if let Err(err) = websocket.close(None).await {
+            warn!("{}", err);
         }
+        return;
+    };
 
-        let message = websocket.read();
+    // Create channel
+    let (tx, mut rx) = mpsc::unbounded_channel();
 
-        if message.is_err() {
-            match message {
-                Err(tungstenite::Error::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {
-                    continue;
-                }
-                _ => {
-                    break;
-                }
-            }
+    // Reconnect into an existing player if the client has a valid session
+    let resumed = hello.session_token
+        .as_deref()
+        .and_then(|token| session::resume(&state.players, token, tx.clone()));
+    let is_resumed = resumed.is_some();
+
+    if !is_resumed && state.players.lock().unwrap().len() >= state.limits.max_players {
+        info!("{} - Server is full", addr);
+        let response = ServerEvent::response("hello", Status::new("error", "Server is full."));
+        if let Err(err) = websocket.send(response.to_message(encoding)).await {
+            warn!("{} - {}", addr, err);
+        }
+        if let Err(err) = websocket.close(None).await {
+            warn!("{}", err);
         }
+        return;
+    }
 
-        // Parse JSON
-        let result = MessageEvent::from_message(message.unwrap());
+    let (player_arc, connection_id) = match resumed {
+        Some(resumed) => {
+            // The player holds the only sender, so a later takeover closes this connection's queue
+            drop(tx);
+            resumed
+        }
+        None => {
+            // Handle ID
+            let my_id = get_unique_id(&state.player_id_counter);
+
+            let player_arc = Arc::new(Mutex::new(Player::new(my_id, tx)));
 
-        if result.is_ok() {
-            let event: MessageEvent = result.unwrap();
+            // Add new player to list
+            state.players.lock().unwrap().push(player_arc.clone());
+            (player_arc, 0)
+        }
+    };
+    let (my_id, session_token) = {
+        let player_guard = player_arc.lock().unwrap();
+        (player_guard.id, player_guard.session_token.clone())
+    };
 
-            println!("{} - {}", addr, event.event);
+    let mut rate_limiter = RateLimiter::new(addr.ip());
+    let mut heartbeat = Heartbeat::new((*state.heartbeat).clone(), Instant::now());
 
-            let mut response = MessageEvent::new_empty();
+    let (mut sink, mut stream) = websocket.split();
+
+    let send_result = sink.send(
+        ServerEvent::Hello(
+            HelloResponse::new(hello.version, my_id, session_token, is_resumed)
+        ).to_message(encoding)
+    ).await;
+    if let Err(err) = send_result {
+        warn!("{} - {}", addr, err);
+    }
 
-            // Process events
-            match event.event.as_str() {
-                // Get player list
-                "players" => {
-                    broadcast_players(&players);
+    if is_resumed {
+        info!("{} - Resumed session of player {}", addr, my_id);
+        tokio::task::block_in_place(|| {
+            let joined_game = player_arc.lock().unwrap().joined_game.clone();
+            if let Some(game) = joined_game {
+                game.lock().unwrap().rejoin(&player_arc);
+            }
+            broadcast_players(&state.players);
+        });
+    }
+
+    loop {
+        let message = tokio::select! {
+            // Process queue
+            event = rx.recv() => {
+                let Some(event) = event else {
+                    info!("{} - Session taken over by another connection", addr);
+                    break;
+                };
+                if let Err(err) = sink.send(event.to_message(encoding)).await {
+                    warn!("{} - {}", addr, err);
+                    break;
                 }
-                // Broadcast message to all players
-                "broadcast" => {
-                    let json = event.content;
-                    for p in players.lock().unwrap().iter() {
-                        p.lock()
-                            .unwrap()
-                            .tx.send(MessageEvent::new(&String::from("broadcast"), &json))
-                            .unwrap();
+                continue;
+            }
+            message = stream.next() => message,
+            _ = tokio::time::sleep_until(heartbeat.next_deadline().into()) => {
+                match heartbeat.poll(Instant::now()) {
+                    HeartbeatAction::Wait => {}
+                    HeartbeatAction::Ping => {
+                        if let Err(err) = sink.send(Message::Ping(Vec::new())).await {
+                            warn!("{} - {}", addr, err);
+                            break;
+                        }
                     }
-                }
-                // Create new game
-                "create_game" => {
-                    let json = event.content;
-                    let game_parameters = GameCreationData::from_json(json.as_str());
-                    if game_parameters.is_ok() {
-                        let game = Game::new(
-                            &game_parameters.unwrap(),
-                            &game_id_counter,
-                            &player_arc,
-                            &players,
-                            &player_id_counter
-                        );
-                        games.lock().unwrap().push(game);
-                        response = MessageEvent::new("create_game", Status::new("ok", ""));
-                        broadcast_games(&players, &games);
-                    } else {
-                        response = MessageEvent::new(
-                            event.event,
-                            Status::new("error", game_parameters.err().unwrap().to_string())
-                        );
+                    HeartbeatAction::Dead => {
+                        info!("{} - No pong received, connection is dead", addr);
+                        break;
                     }
-                }
-                // Get game list
-                "games" => {
-                    broadcast_games(&players, &games);
-                }
-                // Join game
-                "join_game" => {
-                    response = Player::join_game(&player_arc, &event, &games, &players);
-                }
-                // Make move
-                "move" => {
-                    let json = event.content;
-                    let position = Size::from_json(&json);
-
-                    // Check if json is valid
-                    if position.is_ok() {
-                        // Check player is in a game
-                        if player_arc.lock().unwrap().joined_game.is_some() {
-                            // Clone the Option<Arc<Mutex<Game>>> to prevent the player being locked resulting in the thread waiting forever
-                            let game = player_arc.lock().unwrap().joined_game.clone().unwrap();
-
-                            let game_locked = game.lock().unwrap();
-                            if game_locked.add_move(&player_arc, position.unwrap()) {
-                                response = MessageEvent::new(event.event, Status::new("ok", ""));
-                            } else {
-                                response = MessageEvent::new(
-                                    event.event,
-                                    Status::new("error", "Move not allowed.")
-                                );
-                            }
-                        } else {
-                            response = MessageEvent::new(
-                                event.event,
-                                Status::new("error", "You are not in a game.")
-                            );
-                        }
-                    } else {
-                        response = MessageEvent::new(
-                            event.event,
-                            Status::new("error", position.err().unwrap().to_string())
-                        );
+                    HeartbeatAction::Idle => {
+                        info!("{} - Connection idle for too long", addr);
+                        break;
                     }
                 }
-                "ready" => {
-                    // Check player is in a game
-                    if player_arc.lock().unwrap().joined_game.is_some() {
-                        // Clone the Option<Arc<Mutex<Game>>> to prevent the player being locked resulting in the thread waiting forever
-                        let game = player_arc.lock().unwrap().joined_game.clone().unwrap();
-                        response = MessageEvent::new(
-                            event.event,
-                            game.lock().unwrap().ready_toggle(&player_arc)
-                        );
-                        broadcast_players(&players);
-                    } else {
-                        response = MessageEvent::new(
-                            event.event,
-                            Status::new("error", "You are not in a game.")
-                        );
+                continue;
+            }
+        };
+
+        let message = match message {
+            Some(Ok(message)) => message,
+            _ => {
+                break;
+            }
+        };
+        if message.is_close() {
+            break;
+        }
+        if message.is_pong() {
+            heartbeat.pong_received();
+        }
+        if !message.is_text() && !message.is_binary() {
+            continue;
+        }
+        heartbeat.activity(Instant::now());
+
+        // Parse JSON
+        let response = match ClientMessage::from_message(message, encoding) {
+            Ok(message) => {
+                let name = message.request.name();
+                debug!("{} - {}", addr, name);
+
+                match rate_limiter.check(name, &state.rate_limits, &state.address_limiter) {
+                    RateLimitResult::Allowed => {
+                        // Requests lock the shared state, which can be held for a while by bots that are thinking
+                        tokio::task::block_in_place(|| {
+                            handle_request(message.request, &player_arc, &state)
+                        })
                     }
-                }
-                "get_image" => {
-                    let json = event.content;
-                    let id: i32 = common::from_json(&json).unwrap();
-
-                    let result = common::get_object(&players, |p| p.lock().unwrap().id == id);
-                    if result.is_some() {
-                        response = MessageEvent::new(
-                            "player_image",
-                            PlayerImageResponse::new(
-                                id,
-                                result.unwrap().lock().unwrap().get_image()
+                    RateLimitResult::Limited => {
+                        Some(
+                            ServerEvent::response(
+                                name,
+                                Status::new("error", "Rate limit exceeded.")
                             )
-                        );
-                    } else {
-                        response = MessageEvent::new(
-                            event.event,
-                            Status::new("error", format!("Played with id {} not found.", id))
-                        );
+                        )
                     }
-                }
-                "add_bot" => {
-                    if player_arc.lock().unwrap().joined_game.is_some() {
-                        // Clone the Option<Arc<Mutex<Game>>> to prevent the player being locked resulting in the thread waiting forever
-                        let game = player_arc.lock().unwrap().joined_game.clone().unwrap();
-
-                        let game_locked = game.lock().unwrap();
-                        if game_locked.add_bot(&player_arc, event.content) {
-                            response = MessageEvent::new(event.event, Status::new("ok", ""));
-                        } else {
-                            response = MessageEvent::new(
-                                event.event,
-                                Status::new(
-                                    "error",
-                                    "You are not allowed to add a bot to this game."
-                                )
-                            );
-                        }
-                    } else {
-                        response = MessageEvent::new(
-                            event.event,
-                            Status::new("error", "You are not in a game.")
-                        );
+                    RateLimitResult::Disconnect => {
+                        warn!("{} - Too many rate limited requests, disconnecting", addr);
+                        break;
                     }
-                }
-                "current_state" => {
-                    // Check player is in a game
-                    if player_arc.lock().unwrap().joined_game.is_some() {
-                        // Clone the Option<Arc<Mutex<Game>>> to prevent the player being locked resulting in the thread waiting forever
-                        let game = player_arc.lock().unwrap().joined_game.clone().unwrap();
-                        game.lock().unwrap().request_current_state(&player_arc);
-                    } else {
-                        response = MessageEvent::new(
-                            event.event,
-                            Status::new("error", "You are not in a game.")
-                        );
+                }.map(|response| response.with_request_id(message.request_id))
+            }
+            Err(err) => {
+                // Malformed messages are limited like requests, so they can't flood the log
+                match rate_limiter.check("malformed", &state.rate_limits, &state.address_limiter) {
+                    RateLimitResult::Allowed => {
+                        warn!("{} - {}", addr, err);
+                        Some(
+                            ServerEvent::response(
+                                "unknown",
+                                Status::new("error", format!("Malformed message: {}", err))
+                            )
+                        )
+                    }
+                    RateLimitResult::Limited => {
+                        Some(
+                            ServerEvent::response(
+                                "unknown",
+                                Status::new("error", "Rate limit exceeded.")
+                            )
+                        )
+                    }
+                    RateLimitResult::Disconnect => {
+                        warn!("{} - Too many rate limited requests, disconnecting", addr);
+                        break;
                     }
-                }
-                _ => {
-                    response = MessageEvent::new(
-                        event.event,
-                        Status::new("error", "Unknown event.")
-                    );
                 }
             }
+        };
 
-            // Respond to current request first (might be best to remove in the future)
-            if !response.is_empty() {
-                let send_result = websocket.send(response.to_message());
-                if send_result.is_err() {
-                    println!("{} - {}", addr, send_result.err().unwrap());
-                    break;
-                }
-            }
-        } else {
-            println!("{} - {}", addr, result.err().unwrap());
-            let send_result = websocket.send(
-                MessageEvent::new(
-                    "unknown",
-                    Status::new("error", "Malfomed message JSON.")
-                ).to_message()
-            );
-            if send_result.is_err() {
-                println!("{} - {}", addr, send_result.err().unwrap());
+        // Respond to current request first (might be best to remove in the future)
+        if let Some(response) = response {
+            let send_result = sink.send(response.to_message(encoding)).await;
+            if let Err(err) = send_result {
+                warn!("{} - {}", addr, err);
                 break;
             }
         }
     }
 
+    // Keep the player around for a while so it can reconnect into its seat
+    let disconnected = tokio::task::block_in_place(|| {
+        state.address_limiter.prune();
+        session::disconnect(&player_arc, connection_id)
+    });
+    if disconnected {
+        info!("Player {} disconnected, waiting for reconnect", my_id);
+        tokio::task::block_in_place(|| {
+            let joined_game = player_arc.lock().unwrap().joined_game.clone();
+            if let Some(game) = joined_game {
+                game.lock().unwrap().disconnect_player(&player_arc);
+            }
+            broadcast_players(&state.players);
+        });
+        tokio::spawn(async move {
+            tokio::time::sleep(state.sessions.grace_period).await;
+            tokio::task::block_in_place(|| {
+                if session::is_expired(&player_arc, connection_id) {
+                    remove_player(&player_arc, &state);
+                }
+            });
+        });
+    }
+
+    // Properly close the connection
+    info!("Closing connection {}", addr);
+    let close = sink.close().await;
+    if let Err(err) = close {
+        debug!("{}", err);
+    } else {
+        info!("Connection closed");
+    }
+}
+
+/// Removes the player from its game and from the player list.
+fn remove_player(player_arc: &Arc<Mutex<Player>>, state: &ServerState) {
     let mut player_guard = player_arc.lock().unwrap();
+    let my_id = player_guard.id;
     if player_guard.joined_game.is_some() {
         let game_guard = player_guard.joined_game.as_mut().unwrap().lock().unwrap();
-        game_guard.leave_player(&player_arc);
+        game_guard.leave_player(player_arc);
     }
     drop(player_guard);
 
     // Remove player from list
-    println!("Removing player {}", my_id);
-    let mut players_locked = players.lock().unwrap();
+    info!("Removing player {}", my_id);
+    let mut players_locked = state.players.lock().unwrap();
     let index = players_locked
         .iter()
         .position(|p| p.lock().unwrap().id == my_id)
@@ -328,17 +485,244 @@ fn handle_connection(
     players_locked.swap_remove(index);
     drop(players_locked);
 
-    // Properly close the connection
-    println!("Closing connection {}", addr);
-    let close = websocket.close(None);
-    if close.is_err() {
-        println!("{}", close.err().unwrap().to_string());
-    } else {
-        println!("Connection closed");
+    broadcast_players(&state.players);
+}
+
+/// Waits for the client's `hello` message and checks that its protocol version is supported.
+///
+/// Returns `None` if the client disconnected or has to be rejected, in which case the reason has already been sent to it.
+async fn handshake<S>(
+    websocket: &mut WebSocketStream<S>,
+    addr: SocketAddr,
+    encoding: Encoding
+) -> Option<HelloData>
+    where S: AsyncRead + AsyncWrite + Unpin
+{
+    loop {
+        let message = match websocket.next().await {
+            Some(Ok(message)) => message,
+            _ => {
+                return None;
+            }
+        };
+        if !message.is_text() && !message.is_binary() {
+            continue;
+        }
+
+        let (status, request_id) = match ClientMessage::from_message(message, encoding) {
+            Ok(ClientMessage { request: ClientRequest::Hello(hello), request_id }) => {
+                if hello.version >= MIN_PROTOCOL_VERSION {
+                    debug!("{} - hello v{} {:?}", addr, hello.version, hello.features);
+                    return Some(hello);
+                }
+                (
+                    Status::new(
+                        "error",
+                        format!(
+                            "Protocol version {} is no longer supported, minimum is {}.",
+                            hello.version,
+                            MIN_PROTOCOL_VERSION
+                        )
+                    ),
+                    request_id,
+                )
+            }
+            Ok(message) => (Status::new("error", "Expected hello."), message.request_id),
+            Err(err) => (Status::new("error", format!("Malformed message: {}", err)), None),
+        };
+
+        let response = ServerEvent::response("hello", status).with_request_id(request_id);
+        if let Err(err) = websocket.send(response.to_message(encoding)).await {
+            warn!("{} - {}", addr, err);
+        }
+        return None;
     }
 }
 
-/// Returns the remote IP Address of a &[`TcpStream`] as a [`String`]
-fn get_addr(stream: &TcpStream) -> String {
-    stream.peer_addr().unwrap().to_string()
+/// Processes a single request from a player and returns the direct response to it, if there is one.
+fn handle_request(
+    request: ClientRequest,
+    player_arc: &Arc<Mutex<Player>>,
+    state: &ServerState
+) -> Option<ServerEvent> {
+    let name = request.name();
+    let players = &state.players;
+    let games = &state.games;
+
+    // Clone the Option<Arc<Mutex<Game>>> to prevent the player being locked resulting in the thread waiting forever
+    let joined_game = player_arc.lock().unwrap().joined_game.clone();
+
+    let status = match request {
+        ClientRequest::Hello(_) => Status::new("error", "Handshake already completed."),
+        // Get player list
+        ClientRequest::Players => {
+            broadcast_players(players);
+            return None;
+        }
+        // Broadcast message to all players
+        ClientRequest::Broadcast(content) => {
+            broadcast(players, &ServerEvent::Broadcast(content));
+            return None;
+        }
+        // Create new game
+        ClientRequest::CreateGame(game_parameters) => {
+            if let Err(status) = game_parameters.validate(&state.limits) {
+                return Some(ServerEvent::response(name, status));
+            }
+            if games.lock().unwrap().len() >= state.limits.max_games {
+                return Some(
+                    ServerEvent::response(
+                        name,
+                        Status::new("error", "The maximum number of games has been reached.").with_code(
+                            ErrorCode::TooManyGames
+                        )
+                    )
+                );
+            }
+            let game = Game::new(
+                &game_parameters,
+                &state.game_id_counter,
+                player_arc,
+                players,
+                games,
+                &state.player_id_counter,
+                state.bots,
+                state.game_config
+            );
+            games.lock().unwrap().push(game);
+            broadcast_games(players, games);
+            Status::new("ok", "")
+        }
+        // Get game list
+        ClientRequest::Games => {
+            broadcast_games(players, games);
+            return None;
+        }
+        // Join game
+        ClientRequest::JoinGame(join_data) => {
+            Player::join_game(player_arc, &join_data, games, players)
+        }
+        // Make move
+        ClientRequest::Move(position) => {
+            match joined_game {
+                Some(game) => {
+                    let game_guard = game.lock().unwrap();
+                    if game_guard.is_paused() {
+                        Status::new("error", "Game is paused.")
+                    } else if game_guard.add_move(player_arc, position) {
+                        Status::new("ok", "")
+                    } else {
+                        Status::new("error", "Move not allowed.")
+                    }
+                }
+                None => Status::new("error", "You are not in a game."),
+            }
+        }
+        ClientRequest::Ready => {
+            match joined_game {
+                Some(game) => {
+                    let status = game.lock().unwrap().ready_toggle(player_arc);
+                    broadcast_players(players);
+                    status
+                }
+                None => Status::new("error", "You are not in a game."),
+            }
+        }
+        ClientRequest::GetImage(id) => {
+            match common::get_object(players, |p| p.lock().unwrap().id == id) {
+                Some(player) => {
+                    let image = player.lock().unwrap().get_image();
+                    return Some(ServerEvent::PlayerImage(PlayerImageResponse::new(id, image)));
+                }
+                None => Status::new("error", format!("Played with id {} not found.", id)),
+            }
+        }
+        ClientRequest::AddBot(data) => {
+            match joined_game {
+                Some(game) => {
+                    if game.lock().unwrap().add_bot(player_arc, data.bot_type) {
+                        Status::new("ok", "")
+                    } else {
+                        Status::new("error", "You are not allowed to add a bot to this game.")
+                    }
+                }
+                None => Status::new("error", "You are not in a game."),
+            }
+        }
+        ClientRequest::SpectateGame(join_data) => {
+            Player::spectate_game(player_arc, &join_data, games)
+        }
+        ClientRequest::LeaveGame => {
+            match joined_game {
+                Some(game) => {
+                    game.lock().unwrap().leave_player(player_arc);
+                    Status::new("ok", "")
+                }
+                None => Status::new("error", "You are not in a game."),
+            }
+        }
+        ClientRequest::KickPlayer(target_id) | ClientRequest::BanPlayer(target_id) => {
+            let ban = matches!(request, ClientRequest::BanPlayer(_));
+            match joined_game {
+                Some(game) => {
+                    match common::get_object(players, |p| p.lock().unwrap().id == target_id) {
+                        Some(target) => game.lock().unwrap().kick_player(player_arc, &target, ban),
+                        None => Status::new("error", "Player does not exist."),
+                    }
+                }
+                None => Status::new("error", "You are not in a game."),
+            }
+        }
+        ClientRequest::UndoRequest => {
+            match joined_game {
+                Some(game) => game.lock().unwrap().request_undo(player_arc),
+                None => Status::new("error", "You are not in a game."),
+            }
+        }
+        ClientRequest::UndoResponse(accept) => {
+            match joined_game {
+                Some(game) => game.lock().unwrap().respond_undo(player_arc, accept),
+                None => Status::new("error", "You are not in a game."),
+            }
+        }
+        ClientRequest::UpdateGame(data) => {
+            match joined_game {
+                Some(game) => {
+                    let status = game
+                        .lock()
+                        .unwrap()
+                        .update(player_arc, &data, &state.limits, players);
+                    if status.status == "ok" {
+                        broadcast_players(players);
+                        broadcast_games(players, games);
+                    }
+                    status
+                }
+                None => Status::new("error", "You are not in a game."),
+            }
+        }
+        ClientRequest::PauseRequest => {
+            match joined_game {
+                Some(game) => game.lock().unwrap().request_pause(player_arc),
+                None => Status::new("error", "You are not in a game."),
+            }
+        }
+        ClientRequest::Resume => {
+            match joined_game {
+                Some(game) => game.lock().unwrap().resume(player_arc),
+                None => Status::new("error", "You are not in a game."),
+            }
+        }
+        ClientRequest::CurrentState => {
+            match joined_game {
+                Some(game) => {
+                    game.lock().unwrap().request_current_state(player_arc);
+                    return None;
+                }
+                None => Status::new("error", "You are not in a game."),
+            }
+        }
+    };
+
+    Some(ServerEvent::response(name, status))
 }
@@ -1,83 +1,561 @@
 use std::sync::{ Arc, Mutex };
 
+use log::debug;
 use serde::{ Serialize, Deserialize };
 use tungstenite::Message;
 
-use crate::{ common::{ Size, from_json }, player::Player, game::Game };
+use crate::{
+    bot::Bot,
+    common::{ Size, from_json },
+    config::ServerLimits,
+    player::{ Player, PlayerInfo },
+    game::{ Game, GameInfo },
+    grid::Grid,
+    player_move::PlayerMove,
+};
 
-#[derive(Deserialize, Serialize, Clone)]
-pub(crate) struct MessageEvent {
-    pub event: String,
-    pub content: String,
+/// Version of the protocol spoken by this server.
+pub(crate) const PROTOCOL_VERSION: u32 = 3;
+/// Oldest client protocol version the server still accepts.
+pub(crate) const MIN_PROTOCOL_VERSION: u32 = 3;
+
+/// Wire encoding of the messages on a connection, negotiated through the `Sec-WebSocket-Protocol` header.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Encoding {
+    /// JSON text frames. Used when the client does not request a subprotocol.
+    Json,
+    /// MessagePack binary frames, with structs encoded as maps.
+    MessagePack,
 }
-impl MessageEvent {
-    pub fn new(event: impl Into<String>, content: impl Into<String>) -> Self {
-        Self {
-            event: event.into(),
-            content: content.into(),
+impl Encoding {
+    pub const ALL: [Encoding; 2] = [Encoding::Json, Encoding::MessagePack];
+
+    /// Returns the name of the encoding as announced in the `hello` event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::MessagePack => "msgpack",
         }
     }
-    pub fn new_empty() -> Self {
-        Self {
-            event: String::from(""),
-            content: String::from(""),
+    /// Returns the WebSocket subprotocol selecting this encoding.
+    pub fn subprotocol(&self) -> &'static str {
+        match self {
+            Self::Json => "tictactoe.json",
+            Self::MessagePack => "tictactoe.msgpack",
         }
     }
-    pub fn from_message(message: Message) -> Result<Self, String> {
-        Self::from_json(message.to_text().unwrap())
+    /// Picks the first supported encoding from the value of a `Sec-WebSocket-Protocol` header.
+    pub fn from_subprotocols(header: &str) -> Option<Self> {
+        header
+            .split(',')
+            .map(|p| p.trim())
+            .find_map(|p| Self::ALL.into_iter().find(|e| e.subprotocol() == p))
+    }
+}
+
+/// A message received from a client: a [`ClientRequest`] together with the metadata sent alongside it.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub(crate) struct ClientMessage {
+    /// Identifier chosen by the client, echoed back in the [`Status`] answering the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+    #[serde(flatten)]
+    pub request: ClientRequest,
+}
+impl ClientMessage {
+    pub fn from_message(message: Message, encoding: Encoding) -> Result<Self, String> {
+        match encoding {
+            Encoding::Json => Self::from_json(message.to_text().map_err(|e| e.to_string())?),
+            Encoding::MessagePack => {
+                rmp_serde::from_slice(&message.into_data()).map_err(|e| e.to_string())
+            }
+        }
     }
     pub fn from_json(text: &str) -> Result<Self, String> {
         from_json(text)
     }
-    pub fn to_message(&self) -> Message {
-        Message::Text(String::from(serde_json::to_string(&self).unwrap()))
+}
+
+/// A request sent by a client.
+///
+/// Serialized as `{"event": "<name>", "content": <payload>}`, where the payload is a nested JSON value.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "event", content = "content", rename_all = "snake_case")]
+pub(crate) enum ClientRequest {
+    Hello(HelloData),
+    Players,
+    Broadcast(serde_json::Value),
+    CreateGame(GameCreationData),
+    Games,
+    JoinGame(GameJoinData),
+    Move(Size),
+    Ready,
+    GetImage(i32),
+    AddBot(AddBotData),
+    CurrentState,
+    LeaveGame,
+    SpectateGame(GameJoinData),
+    /// Removes a player from the game. Only allowed to the creator.
+    KickPlayer(i32),
+    /// Removes a player from the game and prevents it from joining again. Only allowed to the creator.
+    BanPlayer(i32),
+    /// Asks the other players of the game to take back the last move.
+    UndoRequest,
+    /// Accepts or declines the pending `undo_request`.
+    UndoResponse(bool),
+    /// Changes the settings of a game that hasn't started yet. Only allowed to the creator.
+    UpdateGame(GameUpdateData),
+    /// Asks to pause the game. The game is paused once every human player asked for it.
+    PauseRequest,
+    /// Resumes a paused game, or withdraws the pending pause requests.
+    Resume,
+}
+impl ClientRequest {
+    /// Returns the event name of the request as it appears on the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Hello(_) => "hello",
+            Self::Players => "players",
+            Self::Broadcast(_) => "broadcast",
+            Self::CreateGame(_) => "create_game",
+            Self::Games => "games",
+            Self::JoinGame(_) => "join_game",
+            Self::Move(_) => "move",
+            Self::Ready => "ready",
+            Self::GetImage(_) => "get_image",
+            Self::AddBot(_) => "add_bot",
+            Self::CurrentState => "current_state",
+            Self::LeaveGame => "leave_game",
+            Self::SpectateGame(_) => "spectate_game",
+            Self::KickPlayer(_) => "kick_player",
+            Self::BanPlayer(_) => "ban_player",
+            Self::UndoRequest => "undo_request",
+            Self::UndoResponse(_) => "undo_response",
+            Self::UpdateGame(_) => "update_game",
+            Self::PauseRequest => "pause_request",
+            Self::Resume => "resume",
+        }
+    }
+}
+
+/// An event sent by the server, either as a response to a [`ClientRequest`] or unsolicited.
+///
+/// Serialized the same way as [`ClientRequest`].
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", content = "content", rename_all = "snake_case")]
+pub(crate) enum ServerEvent {
+    Status(Status),
+    Hello(HelloResponse),
+    Players(Vec<PlayerInfo>),
+    Games(Vec<GameInfo>),
+    Broadcast(serde_json::Value),
+    JoinedGame(GameJoinData),
+    /// Sent to a player that started watching a game.
+    SpectatingGame(GameJoinData),
+    /// Sent to a player that left a game, or was removed from it.
+    LeftGame(GameJoinData),
+    PlayerImage(PlayerImageResponse),
+    CurrentState(Grid),
+    Turn(TurnData),
+    NewMove(PlayerMove),
+    TurnTimeout(TurnTimeoutData),
+    RoundOver(RoundOverData),
+    GameOver(GameOverData),
+    /// A player asks to take back the last move. The other players answer with `undo_response`.
+    UndoRequested(UndoData),
+    /// A player declined the pending undo request, which is dropped.
+    UndoDeclined(UndoData),
+    /// Sent to the players of a game after its creator changed its settings.
+    GameUpdated(GameInfo),
+    /// Sent whenever a game is paused or resumed, or a player asks for a pause.
+    Paused(PauseData),
+}
+impl ServerEvent {
+    /// Creates a [`ServerEvent::Status`] answering the request with the given event name.
+    pub fn response(request: impl Into<String>, mut status: Status) -> Self {
+        status.request = request.into();
+        Self::Status(status)
     }
-    pub fn is_empty(&self) -> bool {
-        self.event.as_str() == ""
+    /// Attaches the client's request id to the event if it is a [`ServerEvent::Status`].
+    pub fn with_request_id(mut self, request_id: Option<u64>) -> Self {
+        if let Self::Status(status) = &mut self {
+            status.request_id = request_id;
+        }
+        self
+    }
+    pub fn to_message(&self, encoding: Encoding) -> Message {
+        match encoding {
+            Encoding::Json => Message::Text(serde_json::to_string(&self).unwrap()),
+            Encoding::MessagePack => Message::Binary(rmp_serde::to_vec_named(&self).unwrap()),
+        }
+    }
+}
+
+/// Sent by the client as the first message of a connection.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub(crate) struct HelloData {
+    pub version: u32,
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Token from a previous `hello` response, used to reconnect into the same player.
+    #[serde(default)]
+    pub session_token: Option<String>,
+}
+
+/// The server's answer to [`HelloData`].
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct HelloResponse {
+    /// Protocol version the connection will use, never higher than the one requested by the client.
+    pub version: u32,
+    pub features: ServerFeatures,
+    pub player_id: i32,
+    /// Token the client can send in a later `hello` to reconnect into the same player.
+    pub session_token: String,
+    /// True if the connection took over an existing player.
+    pub resumed: bool,
+}
+impl HelloResponse {
+    pub fn new(client_version: u32, player_id: i32, session_token: String, resumed: bool) -> Self {
+        Self {
+            version: client_version.min(PROTOCOL_VERSION),
+            features: ServerFeatures::current(),
+            player_id,
+            session_token,
+            resumed,
+        }
     }
 }
 
-#[derive(Deserialize)]
+/// Features supported by this server, announced during the handshake.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct ServerFeatures {
+    pub bot_types: Vec<String>,
+    pub rule_variants: Vec<String>,
+    pub encodings: Vec<String>,
+}
+impl ServerFeatures {
+    pub fn current() -> Self {
+        Self {
+            bot_types: Bot::TYPES.iter().map(|t| t.to_string()).collect(),
+            rule_variants: vec![
+                "hotjoin".to_string(),
+                "match_target".to_string(),
+                "time_control".to_string(),
+                "visibility".to_string(),
+                "round_end".to_string(),
+                "turn_order".to_string(),
+                "casual".to_string()
+            ],
+            encodings: Encoding::ALL.iter()
+                .map(|e| e.name().to_string())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub(crate) struct GameCreationData {
     pub size: Size,
     pub hotjoin: bool,
     pub player_limit: usize,
     pub length_to_win: u32,
+    /// When the match ends. Without a target the game goes on until everyone leaves.
+    #[serde(default)]
+    pub match_target: Option<MatchTarget>,
+    #[serde(default)]
+    pub time_control: Option<TimeControl>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub round_end: RoundEnd,
+    #[serde(default)]
+    pub turn_order: TurnOrder,
+    /// In casual games, the creator can pause the game without the consent of the other players.
+    #[serde(default)]
+    pub casual: bool,
 }
 impl GameCreationData {
+    /// Checks the settings against the server limits.
+    ///
+    /// # Returns
+    ///
+    /// An error status naming the offending setting in its code if the settings are invalid.
+    pub fn validate(&self, limits: &ServerLimits) -> Result<(), Status> {
+        let size = self.size;
+        if size.x == 0 || size.y == 0 {
+            return Err(
+                Status::new("error", "The board must be at least 1x1.").with_code(
+                    ErrorCode::InvalidSize
+                )
+            );
+        }
+        if size.x > limits.max_board_size || size.y > limits.max_board_size {
+            return Err(
+                Status::new(
+                    "error",
+                    format!("Board is too large, the maximum size is {}.", limits.max_board_size)
+                ).with_code(ErrorCode::BoardTooLarge)
+            );
+        }
+        if self.length_to_win == 0 || self.length_to_win > size.x.max(size.y) {
+            return Err(
+                Status::new(
+                    "error",
+                    "The length to win must be at least 1 and fit on the board."
+                ).with_code(ErrorCode::InvalidWinLength)
+            );
+        }
+        if self.player_limit == 0 || self.player_limit > limits.max_player_limit {
+            return Err(
+                Status::new(
+                    "error",
+                    format!("The player limit must be between 1 and {}.", limits.max_player_limit)
+                ).with_code(ErrorCode::InvalidPlayerLimit)
+            );
+        }
+        if
+            self.time_control.is_some_and(|tc| {
+                [tc.move_time_ms, tc.initial_ms].into_iter().flatten().any(|t| t < MIN_TURN_TIME_MS)
+            })
+        {
+            return Err(
+                Status::new(
+                    "error",
+                    format!("Move and initial times must be at least {} ms.", MIN_TURN_TIME_MS)
+                ).with_code(ErrorCode::InvalidTimeControl)
+            );
+        }
+        if matches!(self.match_target, Some(MatchTarget::Rounds(0) | MatchTarget::Points(0))) {
+            return Err(
+                Status::new("error", "The match target must be at least 1.").with_code(
+                    ErrorCode::InvalidMatchTarget
+                )
+            );
+        }
+        Ok(())
+    }
     #[cfg(test)]
     pub fn new(size: Size, hotjoin: bool, player_limit: usize, length_to_win: u32) -> Self {
         Self {
-            size: size,
-            hotjoin: hotjoin,
-            player_limit: player_limit,
-            length_to_win: length_to_win,
+            size,
+            hotjoin,
+            player_limit,
+            length_to_win,
+            match_target: None,
+            time_control: None,
+            visibility: Visibility::Public,
+            round_end: RoundEnd::Continue,
+            turn_order: TurnOrder::Fixed,
+            casual: false,
         }
     }
-    pub fn from_json(text: &str) -> Result<Self, String> {
-        from_json(text)
-    }
 }
 
-#[derive(Deserialize, Serialize)]
+/// Condition ending a match, serialized as `{"rounds": 3}` or `{"points": 5}`.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MatchTarget {
+    /// The match ends after this many rounds, a round ends when no player can complete another line.
+    Rounds(u32),
+    /// The match ends as soon as a player has this many points.
+    Points(u32),
+}
+
+/// New settings of a game. Settings that are left out stay unchanged.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct GameUpdateData {
+    pub size: Option<Size>,
+    pub hotjoin: Option<bool>,
+    pub player_limit: Option<usize>,
+    pub length_to_win: Option<u32>,
+}
+
+/// When a round ends.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RoundEnd {
+    /// Completed lines are blocked and the round goes on until no player can complete another line.
+    #[default]
+    Continue,
+    /// The first completed line wins the round.
+    FirstLine,
+}
+
+/// Which player makes the first move of a round.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TurnOrder {
+    /// The player in the lowest seat.
+    #[default]
+    Fixed,
+    /// A random player.
+    Random,
+    /// Each player in turn, starting with the player in the lowest seat.
+    Rotate,
+    /// The player with the fewest points in the previous round. Rotates after a draw.
+    LoserStarts,
+    /// The winner of the previous round. Rotates after a draw.
+    WinnerStarts,
+}
+
+/// Who can find and join a game, serialized as `{"type": "password", "password": "..."}`.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Visibility {
+    /// Listed in the lobby, anyone can join.
+    #[default]
+    Public,
+    /// Hidden from the lobby. Joining needs the invite code generated by the server.
+    Unlisted,
+    /// Listed in the lobby, joining needs the password.
+    Password {
+        password: String,
+    },
+}
+
+/// Shortest move or initial time a game can be created with, shorter turns would time out right away.
+pub(crate) const MIN_TURN_TIME_MS: u64 = 100;
+
+/// Time limits of a game. All times are in milliseconds.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub(crate) struct TimeControl {
+    /// Maximum time a player can take for a single move.
+    #[serde(default)]
+    pub move_time_ms: Option<u64>,
+    /// Time bank every player starts with. Enables the chess clock: the time taken for a move is subtracted from
+    /// the bank and the player runs out of time when it is empty.
+    #[serde(default)]
+    pub initial_ms: Option<u64>,
+    /// Time added to a player's bank after each of their moves.
+    #[serde(default)]
+    pub increment_ms: u64,
+    #[serde(default)]
+    pub on_timeout: TimeoutAction,
+}
+
+/// What happens to a player that runs out of time.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TimeoutAction {
+    /// The player loses the turn.
+    #[default]
+    Skip,
+    /// The player is removed from the game.
+    Forfeit,
+}
+
+/// Content of the `turn` event.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct TurnData {
+    /// The player whose turn it is.
+    pub player_id: i32,
+    /// Time left until the turn times out, if the game has time controls.
+    pub time_left_ms: Option<u64>,
+    /// Remaining time bank of every player, empty if the game has no chess clock.
+    pub clocks: Vec<ClockData>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct ClockData {
+    pub player_id: i32,
+    pub remaining_ms: u64,
+}
+
+/// Content of the `turn_timeout` event, sent when a player runs out of time.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct TurnTimeoutData {
+    pub player_id: i32,
+    pub action: TimeoutAction,
+}
+
+/// Content of the `round_over` event, sent before the board is cleared for the next round.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct RoundOverData {
+    /// Number of the round that ended, starting at 1.
+    pub round: u32,
+    pub reason: RoundOverReason,
+    /// The player that won the round, if any.
+    pub winner: Option<i32>,
+    /// Fields of the line that ended the round, empty unless the reason is `line`.
+    pub line: Vec<Size>,
+    /// Points every player of the game scored in this round.
+    pub scores: Vec<RoundScore>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RoundOverReason {
+    /// A completed line ended the round, its player wins it.
+    Line,
+    /// No player can complete another line. The player with the most points in the round wins it.
+    Points,
+    /// No player can complete another line and no single player has the most points in the round.
+    Draw,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct RoundScore {
+    pub player_id: i32,
+    pub score: u32,
+}
+
+/// Final result of a player in a finished game.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct Standing {
+    pub player_id: i32,
+    /// Points scored in this game.
+    pub score: u32,
+    /// 1 for the winner. Players with the same score share a rank.
+    pub rank: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct GameOverData {
+    pub game_id: u32,
+    pub rounds_played: u32,
+    /// Sorted from the best player to the worst.
+    pub standings: Vec<Standing>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct PauseData {
+    pub paused: bool,
+    /// Players that asked for a pause which hasn't started yet.
+    pub requested_by: Vec<i32>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct UndoData {
+    pub player_id: i32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub(crate) struct GameJoinData {
     pub id: u32,
+    /// Password of a password protected game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Invite code of an unlisted game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invite_code: Option<String>,
 }
 impl GameJoinData {
     pub fn new(id: u32) -> Self {
         Self {
-            id: id,
+            id,
+            password: None,
+            invite_code: None,
         }
     }
-    pub fn from_json(text: &str) -> Result<Self, String> {
-        from_json(text)
-    }
 }
-impl From<GameJoinData> for String {
-    fn from(value: GameJoinData) -> Self {
-        String::from(serde_json::to_string(&value).unwrap())
-    }
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub(crate) struct AddBotData {
+    #[serde(default)]
+    pub bot_type: Option<String>,
 }
+
 pub(crate) struct InternalMessage {
     pub kind: InternalMessageKind,
     pub player: Arc<Mutex<Player>>,
@@ -89,7 +567,7 @@ impl InternalMessage {
     pub fn new_join(player: Arc<Mutex<Player>>) -> Self {
         Self {
             kind: InternalMessageKind::PlayerJoin,
-            player: player,
+            player,
             position: None,
             bot_type: None,
         }
@@ -97,7 +575,7 @@ impl InternalMessage {
     pub fn new_move(player: Arc<Mutex<Player>>, pos: Size) -> Self {
         Self {
             kind: InternalMessageKind::PlayerMove,
-            player: player,
+            player,
             position: Some(pos),
             bot_type: None,
         }
@@ -105,7 +583,15 @@ impl InternalMessage {
     pub fn new_leave(player: Arc<Mutex<Player>>) -> Self {
         Self {
             kind: InternalMessageKind::PlayerLeave,
-            player: player,
+            player,
+            position: None,
+            bot_type: None,
+        }
+    }
+    pub fn new_disconnect(player: Arc<Mutex<Player>>) -> Self {
+        Self {
+            kind: InternalMessageKind::PlayerDisconnect,
+            player,
             position: None,
             bot_type: None,
         }
@@ -113,23 +599,91 @@ impl InternalMessage {
     pub fn new_ready(player: Arc<Mutex<Player>>) -> Self {
         Self {
             kind: InternalMessageKind::PlayerReady,
-            player: player,
+            player,
             position: None,
             bot_type: None,
         }
     }
-    pub fn new_add_bot(player: Arc<Mutex<Player>>, bot_type: String) -> Self {
+    pub fn new_add_bot(player: Arc<Mutex<Player>>, bot_type: Option<String>) -> Self {
         Self {
             kind: InternalMessageKind::AddBot,
-            player: player,
+            player,
             position: None,
-            bot_type: Some(bot_type),
+            bot_type,
         }
     }
     pub fn new_current_state(player: Arc<Mutex<Player>>) -> Self {
         Self {
             kind: InternalMessageKind::CurrentState,
-            player: player,
+            player,
+            position: None,
+            bot_type: None,
+        }
+    }
+    pub fn new_rejoin(player: Arc<Mutex<Player>>) -> Self {
+        Self {
+            kind: InternalMessageKind::Rejoin,
+            player,
+            position: None,
+            bot_type: None,
+        }
+    }
+    pub fn new_spectate(player: Arc<Mutex<Player>>) -> Self {
+        Self {
+            kind: InternalMessageKind::Spectate,
+            player,
+            position: None,
+            bot_type: None,
+        }
+    }
+    pub fn new_kick(player: Arc<Mutex<Player>>) -> Self {
+        Self {
+            kind: InternalMessageKind::Kick,
+            player,
+            position: None,
+            bot_type: None,
+        }
+    }
+    pub fn new_ban(player: Arc<Mutex<Player>>) -> Self {
+        Self {
+            kind: InternalMessageKind::Ban,
+            player,
+            position: None,
+            bot_type: None,
+        }
+    }
+    pub fn new_pause_request(player: Arc<Mutex<Player>>) -> Self {
+        Self {
+            kind: InternalMessageKind::PauseRequest,
+            player,
+            position: None,
+            bot_type: None,
+        }
+    }
+    pub fn new_resume(player: Arc<Mutex<Player>>) -> Self {
+        Self {
+            kind: InternalMessageKind::Resume,
+            player,
+            position: None,
+            bot_type: None,
+        }
+    }
+    pub fn new_undo_request(player: Arc<Mutex<Player>>) -> Self {
+        Self {
+            kind: InternalMessageKind::UndoRequest,
+            player,
+            position: None,
+            bot_type: None,
+        }
+    }
+    pub fn new_undo_response(player: Arc<Mutex<Player>>, accept: bool) -> Self {
+        Self {
+            kind: if accept {
+                InternalMessageKind::UndoAccept
+            } else {
+                InternalMessageKind::UndoDecline
+            },
+            player,
             position: None,
             bot_type: None,
         }
@@ -140,66 +694,124 @@ pub(crate) enum InternalMessageKind {
     PlayerJoin,
     PlayerMove,
     PlayerLeave,
+    PlayerDisconnect,
     PlayerReady,
     AddBot,
     CurrentState,
+    Rejoin,
+    Spectate,
+    Kick,
+    Ban,
+    UndoRequest,
+    UndoAccept,
+    UndoDecline,
+    PauseRequest,
+    Resume,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone, Debug)]
 pub(crate) struct Status {
+    /// Event name of the request this status answers.
+    pub request: String,
+    /// Id of the request this status answers, if the client sent one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
     pub status: String,
     pub details: String,
+    /// Machine readable reason of an error, for errors a client may want to handle.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
 }
 
 impl Status {
     pub fn new(status: impl Into<String>, details: impl Into<String>) -> Self {
         Self {
+            request: String::new(),
+            request_id: None,
             status: status.into(),
             details: details.into(),
+            code: None,
         }
     }
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+        self
+    }
 }
 
-impl From<Status> for String {
-    fn from(value: Status) -> Self {
-        serde_json::to_string(&value).unwrap()
-    }
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorCode {
+    /// The board has no fields.
+    InvalidSize,
+    /// The board exceeds the server's `max_board_size`.
+    BoardTooLarge,
+    /// The length to win is zero or longer than the board.
+    InvalidWinLength,
+    /// The player limit is zero or exceeds the server's `max_player_limit`.
+    InvalidPlayerLimit,
+    /// The move or initial time is shorter than [`MIN_TURN_TIME_MS`].
+    InvalidTimeControl,
+    /// The match target is zero rounds or points.
+    InvalidMatchTarget,
+    /// The server's `max_games` has been reached.
+    TooManyGames,
 }
 
 pub(crate) fn broadcast_players(players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>) {
     let players_guard = players.lock().unwrap();
-    let json = serde_json::to_string(&*players_guard).unwrap();
+    let list = players_guard
+        .iter()
+        .map(|p| p.lock().unwrap().info())
+        .collect();
     drop(players_guard);
 
-    let response = MessageEvent::new(&String::from("players"), &json);
-
-    broadcast(players, &response)
+    broadcast(players, &ServerEvent::Players(list))
 }
 pub(crate) fn broadcast_games(
     players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>,
     games: &Arc<Mutex<Vec<Arc<Mutex<Game>>>>>
 ) {
     let games_guard = games.lock().unwrap();
-    let json = serde_json::to_string(&*games_guard).unwrap();
+    let list: Vec<(GameInfo, bool)> = games_guard
+        .iter()
+        .map(|g| {
+            let game_guard = g.lock().unwrap();
+            (game_guard.info(), game_guard.is_listed())
+        })
+        .collect();
     drop(games_guard);
 
-    let response = MessageEvent::new(&String::from("games"), &json);
-
-    broadcast(players, &response)
+    // Unlisted games are only shown to their creator and to the players in them
+    for player in players.lock().unwrap().iter() {
+        let (id, joined_game_id) = {
+            let player_guard = player.lock().unwrap();
+            (player_guard.id, player_guard.joined_game_id)
+        };
+        let visible = list
+            .iter()
+            .filter(|(info, listed)| {
+                *listed || info.creator == id || joined_game_id == Some(info.id)
+            })
+            .map(|(info, _)| info.clone())
+            .collect();
+        send_to_player(player, &ServerEvent::Games(visible));
+    }
 }
-pub(crate) fn broadcast(players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>, message: &MessageEvent) {
+pub(crate) fn broadcast(players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>, message: &ServerEvent) {
     for player in players.lock().unwrap().iter() {
         send_to_player(player, message);
     }
 }
-pub(crate) fn send_to_player(player: &Arc<Mutex<Player>>, message: &MessageEvent) {
+pub(crate) fn send_to_player(player: &Arc<Mutex<Player>>, message: &ServerEvent) {
     let result = player.lock().unwrap().tx.send(message.clone());
-    if result.is_err() {
-        println!("Unable to send message {}", result.err().unwrap());
+    if let Err(err) = result {
+        // Happens while a disconnected player waits for a reconnect
+        debug!("Unable to send message {}", err);
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone, Debug)]
 pub(crate) struct PlayerImageResponse {
     pub id: i32,
     pub image: String,
@@ -208,14 +820,119 @@ pub(crate) struct PlayerImageResponse {
 impl PlayerImageResponse {
     pub fn new(id: i32, image: String) -> Self {
         Self {
-            id: id,
-            image: image,
+            id,
+            image,
         }
     }
 }
 
-impl From<PlayerImageResponse> for String {
-    fn from(value: PlayerImageResponse) -> Self {
-        serde_json::to_string(&value).unwrap()
-    }
+#[test]
+fn parse_client_request() {
+    let message = ClientMessage::from_json(r#"{"event":"move","content":{"x":1,"y":2}}"#).unwrap();
+    assert!(matches!(message.request, ClientRequest::Move(pos) if pos == Size::new(1, 2)));
+    assert_eq!(message.request_id, None);
+
+    let message = ClientMessage::from_json(r#"{"event":"ready","request_id":7}"#).unwrap();
+    assert!(matches!(message.request, ClientRequest::Ready));
+    assert_eq!(message.request_id, Some(7));
+
+    let message = ClientMessage::from_json(
+        r#"{"event":"create_game","content":{"size":{"x":3,"y":3},"hotjoin":true,"player_limit":2,"length_to_win":3}}"#
+    ).unwrap();
+    assert!(matches!(message.request, ClientRequest::CreateGame(data) if data.player_limit == 2));
+
+    assert!(ClientMessage::from_json(r#"{"event":"move","content":"{\"x\":1,\"y\":2}"}"#).is_err());
+    assert!(ClientMessage::from_json(r#"{"event":"unknown_event"}"#).is_err());
+}
+
+#[test]
+fn message_pack_encoding() {
+    assert_eq!(Encoding::from_subprotocols("foo, tictactoe.msgpack"), Some(Encoding::MessagePack));
+    assert_eq!(Encoding::from_subprotocols("tictactoe.json,tictactoe.msgpack"), Some(Encoding::Json));
+    assert_eq!(Encoding::from_subprotocols("foo"), None);
+
+    let request = ClientMessage {
+        request_id: Some(3),
+        request: ClientRequest::Move(Size::new(4, 5)),
+    };
+    let data = Message::Binary(rmp_serde::to_vec_named(&request).unwrap());
+    let message = ClientMessage::from_message(data, Encoding::MessagePack).unwrap();
+    assert!(matches!(message.request, ClientRequest::Move(pos) if pos == Size::new(4, 5)));
+    assert_eq!(message.request_id, Some(3));
+
+    let event = ServerEvent::JoinedGame(GameJoinData::new(7)).to_message(Encoding::MessagePack);
+    assert!(event.is_binary());
+    let value: serde_json::Value = rmp_serde::from_slice(&event.into_data()).unwrap();
+    assert_eq!(value["event"], "joined_game");
+    assert_eq!(value["content"]["id"], 7);
+}
+
+#[test]
+fn status_echoes_request_id() {
+    let event = ServerEvent::response("move", Status::new("ok", "")).with_request_id(Some(42));
+    let json: serde_json::Value = serde_json::to_value(&event).unwrap();
+    assert_eq!(json["event"], "status");
+    assert_eq!(json["content"]["request"], "move");
+    assert_eq!(json["content"]["request_id"], 42);
+
+    let event = ServerEvent::response("move", Status::new("ok", ""));
+    let json: serde_json::Value = serde_json::to_value(&event).unwrap();
+    assert!(json["content"].get("request_id").is_none());
+}
+
+#[test]
+fn game_creation_validation() {
+    let limits = ServerLimits::default();
+    let code = |parameters: GameCreationData| parameters.validate(&limits).err().and_then(|s| s.code);
+
+    assert_eq!(code(GameCreationData::new(Size::new(3, 3), false, 2, 3)), None);
+    assert_eq!(code(GameCreationData::new(Size::new(1, 5), false, 2, 5)), None);
+    assert_eq!(
+        code(GameCreationData::new(Size::new(0, 0), false, 2, 3)),
+        Some(ErrorCode::InvalidSize)
+    );
+    assert_eq!(
+        code(GameCreationData::new(Size::new(3, 1000), false, 2, 3)),
+        Some(ErrorCode::BoardTooLarge)
+    );
+    assert_eq!(
+        code(GameCreationData::new(Size::new(3, 3), false, 2, 0)),
+        Some(ErrorCode::InvalidWinLength)
+    );
+    assert_eq!(
+        code(GameCreationData::new(Size::new(3, 4), false, 2, 5)),
+        Some(ErrorCode::InvalidWinLength)
+    );
+    assert_eq!(
+        code(GameCreationData::new(Size::new(3, 3), false, 0, 3)),
+        Some(ErrorCode::InvalidPlayerLimit)
+    );
+
+    let with_time_control = |move_time_ms: Option<u64>, initial_ms: Option<u64>| {
+        let mut parameters = GameCreationData::new(Size::new(3, 3), false, 2, 3);
+        parameters.time_control = Some(TimeControl {
+            move_time_ms,
+            initial_ms,
+            increment_ms: 0,
+            on_timeout: TimeoutAction::Skip,
+        });
+        parameters
+    };
+    assert_eq!(code(with_time_control(Some(1000), Some(MIN_TURN_TIME_MS))), None);
+    assert_eq!(code(with_time_control(Some(0), None)), Some(ErrorCode::InvalidTimeControl));
+    assert_eq!(code(with_time_control(None, Some(0))), Some(ErrorCode::InvalidTimeControl));
+
+    let with_match_target = |match_target: MatchTarget| {
+        let mut parameters = GameCreationData::new(Size::new(3, 3), false, 2, 3);
+        parameters.match_target = Some(match_target);
+        parameters
+    };
+    assert_eq!(code(with_match_target(MatchTarget::Rounds(1))), None);
+    assert_eq!(code(with_match_target(MatchTarget::Rounds(0))), Some(ErrorCode::InvalidMatchTarget));
+    assert_eq!(code(with_match_target(MatchTarget::Points(0))), Some(ErrorCode::InvalidMatchTarget));
+
+    let status = GameCreationData::new(Size::new(3, 3), false, 2, 0).validate(&limits).unwrap_err();
+    let json: serde_json::Value = serde_json::to_value(&status).unwrap();
+    assert_eq!(json["code"], "invalid_win_length");
+    assert!(serde_json::to_value(Status::new("ok", "")).unwrap().get("code").is_none());
 }
@@ -1,89 +1,173 @@
-use std::sync::{ mpsc::Sender, Arc, Mutex };
+use std::sync::{ Arc, Mutex };
+use tokio::sync::mpsc::UnboundedSender;
 use image::{ codecs::png::PngEncoder, ImageBuffer, ImageEncoder, Rgba };
 use serde::Serialize;
 use base64::{ prelude::BASE64_STANDARD, Engine };
 
 use crate::{
-    net::{ MessageEvent, GameJoinData, Status, broadcast_players, send_to_player },
+    net::{ GameJoinData, ServerEvent, Status, broadcast_players, send_to_player },
     game::Game,
     common::get_object,
+    session::generate_token,
 };
 
-#[derive(Serialize)]
 pub(crate) struct Player {
     pub id: i32,
-    #[serde(skip_serializing)]
-    pub tx: Sender<MessageEvent>,
-    #[serde(skip_serializing)]
+    pub tx: UnboundedSender<ServerEvent>,
     pub joined_game: Option<Arc<Mutex<Game>>>,
     pub joined_game_id: Option<u32>,
     pub ready: bool,
     pub name: String,
-    #[serde(skip_serializing)]
     image: Option<String>,
-    #[serde(skip_serializing)]
     pub is_bot: bool,
+    /// True if the player watches its `joined_game` instead of playing in it.
+    pub spectating: bool,
     pub score: u32,
+    /// Token allowing the player to reconnect into this player after a dropped connection. Empty for bots.
+    pub session_token: String,
+    /// False while the player's connection is down and the server waits for it to reconnect.
+    pub connected: bool,
+    /// Incremented every time a new connection takes over the player.
+    pub connection_id: u64,
 }
+
+/// The publicly visible part of a [`Player`], sent to clients in the `players` event.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct PlayerInfo {
+    pub id: i32,
+    pub joined_game_id: Option<u32>,
+    pub ready: bool,
+    pub name: String,
+    pub score: u32,
+    pub connected: bool,
+    pub spectating: bool,
+}
+
 impl Player {
-    pub fn new(id: i32, tx: Sender<MessageEvent>) -> Self {
+    pub fn new(id: i32, tx: UnboundedSender<ServerEvent>) -> Self {
         Self {
-            id: id,
-            tx: tx,
+            id,
+            tx,
             joined_game: None,
             joined_game_id: None,
             ready: false,
             name: format!("Player_{}", id),
             image: None,
             is_bot: false,
+            spectating: false,
             score: 0,
+            session_token: generate_token(),
+            connected: true,
+            connection_id: 0,
         }
     }
-    pub fn new_bot(id: i32, tx: Sender<MessageEvent>) -> Self {
+    pub fn new_bot(id: i32, tx: UnboundedSender<ServerEvent>) -> Self {
         Self {
-            id: id,
-            tx: tx,
+            id,
+            tx,
             joined_game: None,
             joined_game_id: None,
             ready: true,
             name: format!("Bot_{}", id),
             image: None,
             is_bot: true,
+            spectating: false,
             score: 0,
+            session_token: String::new(),
+            connected: true,
+            connection_id: 0,
+        }
+    }
+    pub fn info(&self) -> PlayerInfo {
+        PlayerInfo {
+            id: self.id,
+            joined_game_id: self.joined_game_id,
+            ready: self.ready,
+            name: self.name.clone(),
+            score: self.score,
+            connected: self.connected,
+            spectating: self.spectating,
         }
     }
     pub fn join_game(
         player: &Arc<Mutex<Player>>,
-        event: &MessageEvent,
+        join_data: &GameJoinData,
         games: &Arc<Mutex<Vec<Arc<Mutex<Game>>>>>,
         players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>
-    ) -> MessageEvent {
-        let join_data = GameJoinData::from_json(&event.content);
-        if join_data.is_err() {
-            return MessageEvent::new(
-                event.event.clone(),
-                Status::new("error", join_data.err().unwrap().to_string())
-            );
+    ) -> Status {
+        if player.lock().unwrap().joined_game.is_some() {
+            return Status::new("error", "You are already in a game, leave it first.");
         }
 
-        let id = join_data.unwrap().id;
-        let game = get_object(&games, |p| p.lock().unwrap().id == id);
+        let id = join_data.id;
+        let game = get_object(games, |p| p.lock().unwrap().id == id);
 
         // Check if game exists
-        if game.is_none() {
-            return MessageEvent::new(
-                event.event.clone(),
-                Status::new("error", "Game does not exist.")
-            );
+        let Some(game) = game else {
+            return Status::new("error", "Game does not exist.");
+        };
+
+        if game.lock().unwrap().is_banned(player.lock().unwrap().id) {
+            return Status::new("error", "You are banned from this game.");
+        }
+        if let Err(err) = game.lock().unwrap().check_credentials(join_data) {
+            return Status::new("error", err);
+        }
+
+        // The game thread only seats the player later, so a second join must already see this one
+        Self::reserve_game(player, &game, id);
+        if !game.lock().unwrap().join_player(player) {
+            Self::release_game(player);
+            return Status::new("error", "Can't join game.");
         }
 
-        if !game.unwrap().lock().unwrap().join_player(&player) {
-            return MessageEvent::new(event.event.clone(), Status::new("error", "Can't join game."));
+        broadcast_players(players);
+        send_to_player(player, &ServerEvent::JoinedGame(GameJoinData::new(id)));
+        Status::new("ok", "")
+    }
+
+    /// Lets the player watch a game without taking part in it.
+    pub fn spectate_game(
+        player: &Arc<Mutex<Player>>,
+        join_data: &GameJoinData,
+        games: &Arc<Mutex<Vec<Arc<Mutex<Game>>>>>
+    ) -> Status {
+        if player.lock().unwrap().joined_game.is_some() {
+            return Status::new("error", "You are already in a game, leave it first.");
         }
 
-        broadcast_players(&players);
-        send_to_player(&player, &MessageEvent::new("joined_game", GameJoinData::new(id)));
-        MessageEvent::new(event.event.clone(), Status::new("ok", ""))
+        let id = join_data.id;
+        let Some(game) = get_object(games, |p| p.lock().unwrap().id == id) else {
+            return Status::new("error", "Game does not exist.");
+        };
+
+        if game.lock().unwrap().is_banned(player.lock().unwrap().id) {
+            return Status::new("error", "You are banned from this game.");
+        }
+        if let Err(err) = game.lock().unwrap().check_credentials(join_data) {
+            return Status::new("error", err);
+        }
+
+        Self::reserve_game(player, &game, id);
+        if !game.lock().unwrap().spectate_player(player) {
+            Self::release_game(player);
+            return Status::new("error", "Can't spectate game.");
+        }
+
+        send_to_player(player, &ServerEvent::SpectatingGame(GameJoinData::new(id)));
+        Status::new("ok", "")
+    }
+
+    /// Marks the player as in the game while its join is queued for the game thread.
+    fn reserve_game(player: &Arc<Mutex<Player>>, game: &Arc<Mutex<Game>>, id: u32) {
+        let mut player_guard = player.lock().unwrap();
+        player_guard.joined_game = Some(game.clone());
+        player_guard.joined_game_id = Some(id);
+    }
+    fn release_game(player: &Arc<Mutex<Player>>) {
+        let mut player_guard = player.lock().unwrap();
+        player_guard.joined_game = None;
+        player_guard.joined_game_id = None;
     }
 
     /// Returns the player's image encoded in base64.
@@ -10,13 +10,8 @@ pub(crate) struct PlayerMove {
 impl PlayerMove {
     pub fn new(player: i32, pos: Size) -> Self {
         Self {
-            player: player,
+            player,
             position: pos,
         }
     }
 }
-impl Into<String> for &PlayerMove {
-    fn into(self) -> String {
-        serde_json::to_string(self).unwrap()
-    }
-}
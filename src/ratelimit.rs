@@ -0,0 +1,309 @@
+//! Token bucket rate limiting of client events, per connection and per remote address.
+
+use std::{ collections::{ HashMap, VecDeque }, net::IpAddr, sync::Mutex, time::{ Duration, Instant } };
+
+use serde::Deserialize;
+
+use crate::config::seconds;
+
+/// Size and refill rate of a token bucket.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Limit {
+    /// Number of events that can be sent in a burst.
+    pub burst: u32,
+    /// Number of events regained per second.
+    pub per_second: f64,
+}
+impl Limit {
+    pub const fn new(burst: u32, per_second: f64) -> Self {
+        Self {
+            burst,
+            per_second,
+        }
+    }
+}
+
+/// Limits for each event type, keyed by the event name. Messages that can't be parsed count as `malformed` events.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EventLimits {
+    /// Limit for events without an entry in `events`.
+    pub default: Limit,
+    #[serde(default)]
+    pub events: HashMap<String, Limit>,
+}
+impl EventLimits {
+    fn get(&self, event: &str) -> Limit {
+        self.events.get(event).copied().unwrap_or(self.default)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RateLimitConfig {
+    /// Limits applied to every connection separately.
+    pub connection: EventLimits,
+    /// Limits shared by all connections coming from the same address.
+    pub address: EventLimits,
+    /// Number of rejected events within `violation_window` after which a connection is closed.
+    pub max_violations: u32,
+    /// Time after which a rejected event no longer counts towards `max_violations`.
+    #[serde(deserialize_with = "seconds")]
+    pub violation_window: Duration,
+}
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let connection = [
+            ("broadcast", Limit::new(3, 0.5)),
+            ("players", Limit::new(3, 1.0)),
+            ("games", Limit::new(3, 1.0)),
+            ("create_game", Limit::new(3, 0.2)),
+            ("malformed", Limit::new(5, 1.0)),
+        ];
+        let address = [
+            ("broadcast", Limit::new(10, 2.0)),
+            ("players", Limit::new(10, 4.0)),
+            ("games", Limit::new(10, 4.0)),
+            ("create_game", Limit::new(10, 1.0)),
+            ("malformed", Limit::new(20, 4.0)),
+        ];
+        Self {
+            connection: EventLimits {
+                default: Limit::new(20, 10.0),
+                events: connection
+                    .into_iter()
+                    .map(|(e, l)| (e.to_string(), l))
+                    .collect(),
+            },
+            address: EventLimits {
+                default: Limit::new(100, 50.0),
+                events: address
+                    .into_iter()
+                    .map(|(e, l)| (e.to_string(), l))
+                    .collect(),
+            },
+            max_violations: 20,
+            violation_window: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    limit: Limit,
+    tokens: f64,
+    last_refill: Instant,
+}
+impl TokenBucket {
+    fn new(limit: Limit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            last_refill: now,
+        }
+    }
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst as f64);
+        self.last_refill = now;
+    }
+    /// Takes a token from the bucket. Returns false if the bucket is empty.
+    fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.limit.burst as f64
+    }
+}
+
+/// Buckets for every event type seen so far.
+#[derive(Debug, Default)]
+struct Buckets {
+    buckets: HashMap<String, TokenBucket>,
+}
+impl Buckets {
+    fn try_take(&mut self, event: &str, limits: &EventLimits, now: Instant) -> bool {
+        self.buckets
+            .entry(event.to_string())
+            .or_insert_with(|| TokenBucket::new(limits.get(event), now))
+            .try_take(now)
+    }
+}
+
+/// Buckets shared by all connections from the same address.
+#[derive(Debug, Default)]
+pub(crate) struct AddressRateLimiter {
+    addresses: Mutex<HashMap<IpAddr, Buckets>>,
+}
+impl AddressRateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn try_take(&self, addr: IpAddr, event: &str, limits: &EventLimits, now: Instant) -> bool {
+        self.addresses.lock().unwrap().entry(addr).or_default().try_take(event, limits, now)
+    }
+    /// Forgets addresses whose buckets have all refilled, so the map does not grow without bound.
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.addresses
+            .lock()
+            .unwrap()
+            .retain(|_, b| !b.buckets.values_mut().all(|bucket| bucket.is_full(now)));
+    }
+}
+
+/// Outcome of [`RateLimiter::check`].
+#[derive(PartialEq, Debug)]
+pub(crate) enum RateLimitResult {
+    Allowed,
+    /// The event has to be rejected.
+    Limited,
+    /// The event has to be rejected and the connection closed.
+    Disconnect,
+}
+
+/// Rate limiter of a single connection.
+pub(crate) struct RateLimiter {
+    addr: IpAddr,
+    buckets: Buckets,
+    /// When the recent rejected events happened, oldest first.
+    violations: VecDeque<Instant>,
+}
+impl RateLimiter {
+    pub fn new(addr: IpAddr) -> Self {
+        Self {
+            addr,
+            buckets: Buckets::default(),
+            violations: VecDeque::new(),
+        }
+    }
+
+    /// Checks whether the connection may send another event of the given type.
+    ///
+    /// # Arguments
+    ///
+    /// * `event`: Name of the received event.
+    /// * `config`: Limits to apply.
+    /// * `address_limiter`: Buckets shared with other connections from the same address.
+    pub fn check(
+        &mut self,
+        event: &str,
+        config: &RateLimitConfig,
+        address_limiter: &AddressRateLimiter
+    ) -> RateLimitResult {
+        self.check_at(event, config, address_limiter, Instant::now())
+    }
+    fn check_at(
+        &mut self,
+        event: &str,
+        config: &RateLimitConfig,
+        address_limiter: &AddressRateLimiter,
+        now: Instant
+    ) -> RateLimitResult {
+        if
+            self.buckets.try_take(event, &config.connection, now) &&
+            address_limiter.try_take(self.addr, event, &config.address, now)
+        {
+            return RateLimitResult::Allowed;
+        }
+
+        // Only sustained abuse closes the connection, occasional bursts are forgotten
+        while
+            self.violations
+                .front()
+                .is_some_and(|t| now.saturating_duration_since(*t) >= config.violation_window)
+        {
+            self.violations.pop_front();
+        }
+        self.violations.push_back(now);
+        if self.violations.len() > (config.max_violations as usize) {
+            return RateLimitResult::Disconnect;
+        }
+        RateLimitResult::Limited
+    }
+}
+
+#[test]
+fn rate_limit() {
+    let mut config = RateLimitConfig::default();
+    config.connection.events.insert("broadcast".to_string(), Limit::new(2, 0.0));
+    config.address.events.insert("games".to_string(), Limit::new(3, 0.0));
+    config.max_violations = 2;
+    let address_limiter = AddressRateLimiter::new();
+    let addr: IpAddr = "127.0.0.1".parse().unwrap();
+
+    let mut limiter = RateLimiter::new(addr);
+    assert_eq!(limiter.check("broadcast", &config, &address_limiter), RateLimitResult::Allowed);
+    assert_eq!(limiter.check("broadcast", &config, &address_limiter), RateLimitResult::Allowed);
+    assert_eq!(limiter.check("broadcast", &config, &address_limiter), RateLimitResult::Limited);
+    // Other events have their own buckets
+    assert_eq!(limiter.check("move", &config, &address_limiter), RateLimitResult::Allowed);
+
+    // The address limit is shared between connections
+    let mut other = RateLimiter::new(addr);
+    assert_eq!(limiter.check("games", &config, &address_limiter), RateLimitResult::Allowed);
+    assert_eq!(other.check("games", &config, &address_limiter), RateLimitResult::Allowed);
+    assert_eq!(other.check("games", &config, &address_limiter), RateLimitResult::Allowed);
+    assert_eq!(other.check("games", &config, &address_limiter), RateLimitResult::Limited);
+
+    assert_eq!(limiter.check("broadcast", &config, &address_limiter), RateLimitResult::Limited);
+    assert_eq!(
+        limiter.check("broadcast", &config, &address_limiter),
+        RateLimitResult::Disconnect
+    );
+}
+
+#[test]
+fn token_bucket_refill() {
+    let start = Instant::now();
+    let mut bucket = TokenBucket::new(Limit::new(1, 2.0), start);
+    assert!(bucket.try_take(start));
+    assert!(!bucket.try_take(start));
+    assert!(bucket.try_take(start + std::time::Duration::from_millis(500)));
+    assert!(!bucket.is_full(start + std::time::Duration::from_millis(600)));
+    assert!(bucket.is_full(start + std::time::Duration::from_secs(10)));
+}
+
+#[test]
+fn violations_expire() {
+    let mut config = RateLimitConfig::default();
+    config.connection.events.insert("games".to_string(), Limit::new(0, 0.0));
+    config.max_violations = 2;
+    config.violation_window = Duration::from_secs(60);
+    let address_limiter = AddressRateLimiter::new();
+    let mut limiter = RateLimiter::new("127.0.0.1".parse().unwrap());
+    let start = Instant::now();
+    let check = |limiter: &mut RateLimiter, secs: u64| {
+        limiter.check_at("games", &config, &address_limiter, start + Duration::from_secs(secs))
+    };
+
+    assert_eq!(check(&mut limiter, 0), RateLimitResult::Limited);
+    assert_eq!(check(&mut limiter, 30), RateLimitResult::Limited);
+    // The first violation is forgotten by now
+    assert_eq!(check(&mut limiter, 60), RateLimitResult::Limited);
+    assert_eq!(check(&mut limiter, 120), RateLimitResult::Limited);
+    assert_eq!(check(&mut limiter, 121), RateLimitResult::Limited);
+    assert_eq!(check(&mut limiter, 122), RateLimitResult::Disconnect);
+}
+
+#[test]
+fn malformed_messages_are_limited() {
+    let config = RateLimitConfig::default();
+    let address_limiter = AddressRateLimiter::new();
+    let mut limiter = RateLimiter::new("127.0.0.1".parse().unwrap());
+    let now = Instant::now();
+    for _ in 0..5 {
+        assert_eq!(
+            limiter.check_at("malformed", &config, &address_limiter, now),
+            RateLimitResult::Allowed
+        );
+    }
+    assert_eq!(limiter.check_at("malformed", &config, &address_limiter, now), RateLimitResult::Limited);
+}
@@ -0,0 +1,119 @@
+//! Session tokens that let a player reconnect into the same seat after a dropped connection.
+
+use std::{ sync::{ Arc, Mutex }, time::Duration };
+
+use base64::{ prelude::BASE64_URL_SAFE_NO_PAD, Engine };
+use rand::Rng;
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{ config::seconds, net::ServerEvent, player::Player };
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SessionConfig {
+    /// Time a disconnected player is kept in the player list and its game, waiting for a reconnect.
+    #[serde(deserialize_with = "seconds")]
+    pub grace_period: Duration,
+}
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            grace_period: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Generates a new random session token.
+pub(crate) fn generate_token() -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 24]>())
+}
+
+/// Attaches a new connection to the player owning the session token.
+///
+/// If the player is still connected elsewhere, that connection is taken over: its queue is closed, which ends it.
+///
+/// # Arguments
+///
+/// * `players`: All players on the server.
+/// * `token`: The session token sent by the client in its `hello`.
+/// * `tx`: Sender of the new connection's queue.
+///
+/// # Returns
+///
+/// The player and the id of the new connection, or `None` if no player owns the token.
+pub(crate) fn resume(
+    players: &Arc<Mutex<Vec<Arc<Mutex<Player>>>>>,
+    token: &str,
+    tx: UnboundedSender<ServerEvent>
+) -> Option<(Arc<Mutex<Player>>, u64)> {
+    if token.is_empty() {
+        return None;
+    }
+    let player = players
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|p| p.lock().unwrap().session_token == token)
+        .cloned()?;
+
+    let mut player_guard = player.lock().unwrap();
+    player_guard.tx = tx;
+    player_guard.connected = true;
+    player_guard.connection_id += 1;
+    let connection_id = player_guard.connection_id;
+    drop(player_guard);
+
+    Some((player, connection_id))
+}
+
+/// Marks the player as disconnected, unless another connection has already taken over its session.
+///
+/// # Returns
+///
+/// True if the player was marked as disconnected.
+pub(crate) fn disconnect(player: &Arc<Mutex<Player>>, connection_id: u64) -> bool {
+    let mut player_guard = player.lock().unwrap();
+    if player_guard.connection_id != connection_id {
+        return false;
+    }
+    player_guard.connected = false;
+    true
+}
+
+/// Returns true if the player is still disconnected from the given connection, meaning nobody reconnected in the meantime.
+pub(crate) fn is_expired(player: &Arc<Mutex<Player>>, connection_id: u64) -> bool {
+    let player_guard = player.lock().unwrap();
+    player_guard.connection_id == connection_id && !player_guard.connected
+}
+
+#[test]
+fn session_resume() {
+    let players: Arc<Mutex<Vec<Arc<Mutex<Player>>>>> = Arc::new(Mutex::new(Vec::new()));
+    let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+    let player = Arc::new(Mutex::new(Player::new(5, tx)));
+    let token = player.lock().unwrap().session_token.clone();
+    players.lock().unwrap().push(player.clone());
+
+    assert!(disconnect(&player, 0));
+    assert!(!player.lock().unwrap().connected);
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    assert!(resume(&players, "invalid", tx.clone()).is_none());
+    assert!(resume(&players, "", tx.clone()).is_none());
+    let (resumed, connection_id) = resume(&players, &token, tx).unwrap();
+    assert_eq!(resumed.lock().unwrap().id, 5);
+    assert_eq!(connection_id, 1);
+    assert!(resumed.lock().unwrap().connected);
+
+    // The grace period of the old connection must not remove the resumed player
+    assert!(!is_expired(&player, 0));
+    // Neither may the old connection mark it as disconnected
+    assert!(!disconnect(&player, 0));
+
+    resumed.lock().unwrap().tx.send(ServerEvent::JoinedGame(crate::net::GameJoinData::new(5))).unwrap();
+    assert!(matches!(rx.try_recv(), Ok(ServerEvent::JoinedGame(data)) if data.id == 5));
+
+    assert!(disconnect(&player, 1));
+    assert!(is_expired(&player, 1));
+}
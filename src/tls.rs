@@ -0,0 +1,91 @@
+//! Loading of the certificate and key used to serve `wss://` connections.
+
+use std::{ fs::File, io::BufReader, sync::Arc };
+
+use tokio_rustls::{ rustls::ServerConfig, TlsAcceptor };
+
+/// Creates a [`TlsAcceptor`] from a PEM encoded certificate chain and private key.
+///
+/// # Arguments
+///
+/// * `cert_path`: Path to the PEM file containing the certificate chain, leaf certificate first.
+/// * `key_path`: Path to the PEM file containing the private key.
+///
+/// # Returns
+///
+/// The acceptor, or a description of what went wrong while loading the files.
+pub(crate) fn load_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, String> {
+    let cert_file = File::open(cert_path).map_err(|e| format!("{}: {}", cert_path, e))?;
+    let certs = rustls_pemfile
+        ::certs(&mut BufReader::new(cert_file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {}", cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("{}: No certificate found.", cert_path));
+    }
+
+    let key_file = File::open(key_path).map_err(|e| format!("{}: {}", key_path, e))?;
+    let key = rustls_pemfile
+        ::private_key(&mut BufReader::new(key_file))
+        .map_err(|e| format!("{}: {}", key_path, e))?
+        .ok_or(format!("{}: No private key found.", key_path))?;
+
+    let config = ServerConfig::builder_with_provider(
+        Arc::new(tokio_rustls::rustls::crypto::ring::default_provider())
+    )
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| e.to_string())?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+#[tokio::test]
+async fn tls_handshake() {
+    use tokio::{ io::{ AsyncReadExt, AsyncWriteExt }, net::{ TcpListener, TcpStream } };
+    use tokio_rustls::{ rustls::{ pki_types::ServerName, ClientConfig, RootCertStore }, TlsConnector };
+
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = std::env::temp_dir().join(format!("tictactoe-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+    std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+
+    let acceptor = load_acceptor(cert_path.to_str().unwrap(), key_path.to_str().unwrap()).unwrap();
+    assert!(load_acceptor(key_path.to_str().unwrap(), key_path.to_str().unwrap()).is_err());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = acceptor.accept(stream).await.unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        stream.shutdown().await.unwrap();
+    });
+
+    let mut roots = RootCertStore::empty();
+    roots.add(certified.cert.der().clone()).unwrap();
+    let config = ClientConfig::builder_with_provider(
+        Arc::new(tokio_rustls::rustls::crypto::ring::default_provider())
+    )
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = TlsConnector::from(Arc::new(config));
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut stream = connector
+        .connect(ServerName::try_from("localhost").unwrap(), stream).await
+        .unwrap();
+
+    let mut received = String::new();
+    stream.read_to_string(&mut received).await.unwrap();
+    assert_eq!(received, "hello");
+
+    server.await.unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}